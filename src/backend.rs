@@ -0,0 +1,489 @@
+use nix::unistd::{getuid, Uid};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::commands::{self, SettingsContext};
+use crate::config::{BackendKind, Config, ControlTarget, Escalation, ExitMode, Sensor};
+use crate::helper::HelperBackend;
+use crate::hwmon::{HwmonBackend, PwmOutputBackend};
+use crate::nvml::NvmlBackend;
+use crate::shared::{FeedPublisher, FeedReader, SharedTempBackend, SnapshotFeed};
+use crate::sim::SimBackend;
+use crate::stream::NvidiaStreamBackend;
+use crate::trace::ReplayBackend;
+
+/// Telemetry captured from a single query so every value describes the same moment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GpuSnapshot {
+    /// Core (edge) temperature
+    pub temp: u64,
+    pub hotspot_temp: Option<u64>,
+    /// Memory junction temperature
+    pub memory_temp: Option<u64>,
+    pub fan_speed: u64,
+    /// Per-fan speeds in percent, empty when the backend only reports one value
+    pub fan_speeds: Vec<u64>,
+    /// Board power draw in watts
+    pub power_draw: Option<f64>,
+    /// GPU utilization in percent
+    pub utilization: Option<u64>,
+    /// Graphics clock in MHz
+    pub graphics_clock: Option<u64>,
+    /// Memory clock in MHz
+    pub memory_clock: Option<u64>,
+    /// Bitmask of active NVML clock throttle reasons
+    pub throttle_reasons: Option<u64>,
+}
+
+impl fmt::Display for GpuSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} C", self.temp)?;
+        if let Some(hotspot) = self.hotspot_temp {
+            write!(f, ", {} C hotspot", hotspot)?;
+        }
+        if let Some(memory) = self.memory_temp {
+            write!(f, ", {} C memory", memory)?;
+        }
+        write!(f, ", {} %", self.fan_speed)?;
+        if self.fan_speeds.len() > 1 {
+            write!(f, " {:?}", self.fan_speeds)?;
+        }
+        if let Some(power) = self.power_draw {
+            write!(f, ", {:.1} W", power)?;
+        }
+        if let Some(util) = self.utilization {
+            write!(f, ", {} % util", util)?;
+        }
+        if let (Some(gr), Some(mem)) = (self.graphics_clock, self.memory_clock) {
+            write!(f, ", {}/{} MHz", gr, mem)?;
+        }
+        if let Some(reasons) = self.throttle_reasons.filter(|r| *r != 0) {
+            write!(f, ", throttle {:#x}", reasons)?;
+        }
+        Ok(())
+    }
+}
+
+impl GpuSnapshot {
+    pub fn sensor_temp(&self, sensor: Sensor) -> Option<u64> {
+        match sensor {
+            Sensor::Core => Some(self.temp),
+            Sensor::Hotspot => self.hotspot_temp,
+            Sensor::Memory => self.memory_temp,
+        }
+    }
+}
+
+pub trait GpuBackend: Send + Sync {
+    /// Short name of the backend used in log output
+    fn name(&self) -> &'static str;
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>>;
+    /// Current fan speed for capturing the startup state; backends whose reads advance a
+    /// trace, a model or a recording return it without doing so
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(self.get_snapshot()?.fan_speed)
+    }
+    fn get_fan_count(&mut self) -> u64 {
+        1
+    }
+    /// Measured speed of each fan in RPM, `None` when the backend can't read it
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        None
+    }
+    /// Speed of each fan in percent, also for backends whose snapshot only has the first fan's;
+    /// `None` when the backend can't read them
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        None
+    }
+    /// Whether the fans are still under manual control, `None` when the backend can't tell
+    fn get_fan_control(&mut self) -> Option<bool> {
+        None
+    }
+    /// Time until the next reading is due, `None` to poll every `global_delay`
+    fn next_delay(&self) -> Option<Duration> {
+        None
+    }
+    /// `pwmN_enable` a PWM output found when it was first opened, `None` for GPUs
+    fn original_pwm_enable(&self) -> Option<u64> {
+        None
+    }
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>>;
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>>;
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>>;
+    /// Sets a single fan, backends with one fan target treat this like `set_fan_speed`
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        let _ = fan;
+        self.set_fan_speed(speed)
+    }
+}
+
+/// Fan control state found at startup, so it can be put back on exit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InitialFanState {
+    /// `None` when the backend can't report whether the fans were under manual control
+    pub manual_control: Option<bool>,
+    pub fan_speed: u64,
+    pub fan_speeds: Vec<u64>,
+    /// Automatic mode of a PWM output, a backend rebuilt for cleanup would only see manual
+    pub pwm_enable: Option<u64>,
+}
+
+impl InitialFanState {
+    pub fn capture(backend: &mut dyn GpuBackend) -> Self {
+        // without the speeds there's nothing to restore, exit then hands the fans to the driver
+        let pwm_enable = backend.original_pwm_enable();
+        let Ok(fan_speed) = backend.read_fan_speed() else {
+            return InitialFanState {
+                pwm_enable,
+                ..InitialFanState::default()
+            };
+        };
+        InitialFanState {
+            manual_control: backend.get_fan_control(),
+            fan_speed,
+            fan_speeds: backend.get_fan_speeds().unwrap_or_default(),
+            pwm_enable,
+        }
+    }
+}
+
+/// Leaves the fans as `mode` asks before the controller exits
+pub fn apply_exit_mode(
+    backend: &mut dyn GpuBackend,
+    mode: ExitMode,
+    exit_speed: u64,
+    initial: &InitialFanState,
+) -> Result<(), Box<dyn Error>> {
+    match mode {
+        ExitMode::Auto => backend.release_fan_control(),
+        // an unknown initial state is treated as the driver default
+        ExitMode::Restore if initial.manual_control != Some(true) => backend.release_fan_control(),
+        ExitMode::Restore => {
+            backend.acquire_fan_control()?;
+            if initial.fan_speeds.is_empty() {
+                return backend.set_fan_speed(initial.fan_speed);
+            }
+            for (fan, speed) in initial.fan_speeds.iter().enumerate() {
+                backend.set_fan_speed_of(fan as u64, *speed)?;
+            }
+            Ok(())
+        }
+        ExitMode::Speed => {
+            backend.acquire_fan_control()?;
+            backend.set_fan_speed(exit_speed.min(100))
+        }
+    }
+}
+
+/// How long RPMs read through nvidia-settings are reused, stall detection works on a scale
+/// of tens of seconds and a spawn per tick would only add load
+const RPM_READ_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reads telemetry through `nvidia-smi` and drives the fans through `nvidia-settings`
+#[derive(Debug, Clone)]
+pub struct NvidiaCliBackend {
+    pub gpu_id: u8,
+    /// Display, targets and timeout of each nvidia-settings call, the timeout also bounds nvidia-smi
+    pub settings: SettingsContext,
+    fan_count: Option<u64>,
+    /// Last RPM read and when it was taken, a failed read is kept just as long
+    rpms: Option<(Instant, Option<Vec<u64>>)>,
+}
+
+impl NvidiaCliBackend {
+    pub fn new(gpu_id: u8, settings: SettingsContext) -> Self {
+        NvidiaCliBackend {
+            gpu_id,
+            settings,
+            fan_count: None,
+            rpms: None,
+        }
+    }
+}
+
+impl GpuBackend for NvidiaCliBackend {
+    fn name(&self) -> &'static str {
+        "nvidia"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        Ok(commands::get_gpu_snapshot(
+            &self.gpu_id,
+            self.settings.timeout,
+        )?)
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        *self
+            .fan_count
+            .get_or_insert_with(|| commands::get_fan_count(&self.settings))
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        if let Some((read_at, rpms)) = &self.rpms {
+            if read_at.elapsed() < RPM_READ_INTERVAL {
+                return rpms.clone();
+            }
+        }
+        let fan_count = self.get_fan_count();
+        let rpms = commands::get_fan_rpms(&self.settings, fan_count).ok();
+        self.rpms = Some((Instant::now(), rpms.clone()));
+        rpms
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        let fan_count = self.get_fan_count();
+        commands::get_fan_speeds(&self.settings, fan_count)
+            .ok()
+            .filter(|speeds| speeds.len() as u64 == fan_count)
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        commands::get_fan_control(&self.settings).ok()
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(commands::set_fan_control(&self.settings, 1)?)
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(commands::set_fan_control(&self.settings, 0)?)
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        Ok(commands::set_fan_speed(&self.settings, speed)?)
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        Ok(commands::set_fan_speed_of(&self.settings, fan, speed)?)
+    }
+}
+
+/// Builds the backend of every control target. A PWM output following a GPU that has its own
+/// loop reads that loop's snapshots instead of polling the GPU a second time.
+pub fn for_targets(targets: &[ControlTarget]) -> Result<Vec<Box<dyn GpuBackend>>, Box<dyn Error>> {
+    let (gpus, outputs): (Vec<&ControlTarget>, Vec<&ControlTarget>) = targets
+        .iter()
+        .partition(|target| target.pwm_output.is_none());
+    let feeds: Vec<(u8, SnapshotFeed)> = gpus
+        .iter()
+        .map(|gpu| gpu.config.gpu_id)
+        .filter(|gpu_id| outputs.iter().any(|output| output.config.gpu_id == *gpu_id))
+        .map(|gpu_id| (gpu_id, SnapshotFeed::default()))
+        .collect();
+    let feed_of = |gpu_id: u8| {
+        feeds
+            .iter()
+            .find(|(id, _)| *id == gpu_id)
+            .map(|(_, feed)| feed.clone())
+    };
+
+    let mut backends: Vec<Box<dyn GpuBackend>> = Vec::new();
+    for target in targets {
+        let config = &target.config;
+        let backend: Box<dyn GpuBackend> = match (&target.pwm_output, feed_of(config.gpu_id)) {
+            (None, Some(feed)) => Box::new(FeedPublisher::new(from_config(config)?, feed)),
+            (None, None) => from_config(config)?,
+            // a missed poll or two is fine, longer means the GPU's loop can't read it
+            (Some(_), Some(feed)) => {
+                let max_age = Duration::from_secs(config.global_delay * 2 + config.command_timeout);
+                let reader = FeedReader::new(config.gpu_id, feed, max_age);
+                pwm_output_backend(target, Box::new(reader), None)?
+            }
+            // the GPU has no loop of its own, the output reads it directly
+            (Some(_), None) => pwm_output_backend(target, from_config(config)?, None)?,
+        };
+        backends.push(backend);
+    }
+    Ok(backends)
+}
+
+/// Builds a target's backend again for cleanup, `initial` keeps a PWM output's original mode.
+/// Putting a PWM output back never reads its GPU, so it gets an empty feed.
+pub fn for_cleanup(
+    target: &ControlTarget,
+    initial: &InitialFanState,
+) -> Result<Box<dyn GpuBackend>, Box<dyn Error>> {
+    match &target.pwm_output {
+        Some(_) => {
+            let reader = FeedReader::new(
+                target.config.gpu_id,
+                SnapshotFeed::default(),
+                Duration::ZERO,
+            );
+            pwm_output_backend(target, Box::new(reader), Some(initial))
+        }
+        None => from_config(&target.config),
+    }
+}
+
+fn pwm_output_backend(
+    target: &ControlTarget,
+    reader: Box<dyn GpuBackend>,
+    initial: Option<&InitialFanState>,
+) -> Result<Box<dyn GpuBackend>, Box<dyn Error>> {
+    let output = target
+        .pwm_output
+        .as_ref()
+        .ok_or("Not a PWM output target")?;
+    Ok(Box::new(PwmOutputBackend::new(
+        Path::new(&target.config.sysfs_root),
+        &output.hwmon,
+        output.channel,
+        initial.and_then(|initial| initial.pwm_enable),
+        reader,
+    )?))
+}
+
+pub fn from_config(config: &Config) -> Result<Box<dyn GpuBackend>, Box<dyn Error>> {
+    let mut settings = SettingsContext::from_config(config);
+    let drives_nvidia = matches!(
+        config.backend,
+        BackendKind::Nvidia | BackendKind::NvidiaStream | BackendKind::Nvml
+    );
+    if drives_nvidia {
+        resolve_fan_targets(config, &mut settings)?;
+    }
+    let doas_drops_xauthority = config.escalation == Escalation::Doas
+        && config.xauthority.is_some()
+        && config.helper_socket.is_none()
+        && !Uid::is_root(getuid());
+    if drives_nvidia && doas_drops_xauthority {
+        eprintln!(
+            "doas can't be told to keep XAUTHORITY, fan writes only see 'xauthority' with a \
+             'keepenv' or 'setenv {{ XAUTHORITY }}' rule in doas.conf"
+        );
+    }
+    let mut backend = build_backend(config, &settings)?;
+    if let (Some(socket), true) = (&config.helper_socket, drives_nvidia) {
+        backend = Box::new(HelperBackend::new(backend, PathBuf::from(socket), settings));
+    }
+
+    if !config.temp_sources.is_empty() {
+        let mut readers: Vec<(u8, Box<dyn GpuBackend>)> = Vec::new();
+        for source in &config.temp_sources {
+            if source.gpu_id == config.gpu_id || readers.iter().any(|(id, _)| *id == source.gpu_id)
+            {
+                continue;
+            }
+            let source_config = Config {
+                gpu_id: source.gpu_id,
+                settings_gpu_target: None,
+                settings_fan_targets: Vec::new(),
+                ..config.clone()
+            };
+            let source_settings = SettingsContext::from_config(&source_config);
+            readers.push((
+                source.gpu_id,
+                build_backend(&source_config, &source_settings)?,
+            ));
+        }
+        backend = Box::new(SharedTempBackend::new(
+            backend,
+            config.gpu_id,
+            config.temp_sources.clone(),
+            config.temp_combine,
+            readers,
+        ));
+    }
+
+    Ok(backend)
+}
+
+/// Looks up the GPU's own `[fan:N]` targets when none are configured, nvidia-settings numbers
+/// fans across every GPU so a loop would otherwise write and count the other GPUs' fans too
+fn resolve_fan_targets(
+    config: &Config,
+    settings: &mut SettingsContext,
+) -> Result<(), Box<dyn Error>> {
+    if !settings.fan_targets.is_empty() {
+        return Ok(());
+    }
+    match commands::get_gpu_fan_targets(settings) {
+        Ok(fan_targets) => {
+            if !fan_targets.is_empty() {
+                println!(
+                    "GPU {} uses nvidia-settings fans {:?}",
+                    config.gpu_id, fan_targets
+                );
+            }
+            settings.fan_targets = fan_targets;
+            Ok(())
+        }
+        // NVML may not need nvidia-settings at all and the helper writes from its own session
+        Err(e) if config.backend == BackendKind::Nvml || config.helper_socket.is_some() => {
+            eprintln!(
+                "Could not look up the fans of GPU {}: {}; set 'settings_fan_targets' if \
+                 nvidia-settings controls more than one GPU",
+                config.gpu_id, e
+            );
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn build_backend(
+    config: &Config,
+    settings: &SettingsContext,
+) -> Result<Box<dyn GpuBackend>, Box<dyn Error>> {
+    let cli = NvidiaCliBackend::new(config.gpu_id, settings.clone());
+    // the helper runs nvidia-settings in its own session, there's nothing to check here
+    let check_display = || match config.helper_socket {
+        Some(_) => Ok(()),
+        None => commands::check_display(settings),
+    };
+    match config.backend {
+        BackendKind::Nvidia => {
+            check_display()?;
+            Ok(Box::new(cli))
+        }
+        BackendKind::NvidiaStream => {
+            check_display()?;
+            Ok(Box::new(NvidiaStreamBackend::new(
+                Duration::from_secs(config.global_delay),
+                cli,
+            )))
+        }
+        BackendKind::Nvml => {
+            let privileged = Uid::is_root(getuid());
+            match NvmlBackend::load(
+                config.nvml_library_path.as_deref(),
+                config.gpu_id,
+                privileged,
+                cli.clone(),
+            ) {
+                Ok(nvml) => {
+                    if !nvml.writes_via_nvml() {
+                        println!(
+                            "NVML cannot set fan speeds here, using nvidia-settings for writes"
+                        );
+                        check_display()?;
+                    }
+                    Ok(Box::new(nvml))
+                }
+                Err(e) => {
+                    println!("{}, falling back to nvidia-smi/nvidia-settings", e);
+                    check_display()?;
+                    Ok(Box::new(cli))
+                }
+            }
+        }
+        BackendKind::Hwmon => Ok(Box::new(HwmonBackend::new(
+            Path::new(&config.sysfs_root),
+            config.gpu_id,
+        )?)),
+        BackendKind::Sim => Ok(Box::new(SimBackend::new(
+            config.sim.clone().unwrap_or_default(),
+        ))),
+        BackendKind::Replay => {
+            let path = config
+                .replay_trace
+                .as_deref()
+                .ok_or("The replay backend requires 'replay_trace' to be set")?;
+            Ok(Box::new(ReplayBackend::open(Path::new(path))?))
+        }
+    }
+}
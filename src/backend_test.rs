@@ -0,0 +1,102 @@
+use std::error::Error;
+
+use crate::backend::{self, GpuBackend, GpuSnapshot, InitialFanState};
+use crate::config::{ExitMode, SimConfig};
+use crate::sim::SimBackend;
+
+fn sim() -> SimBackend {
+    SimBackend::new(SimConfig {
+        time_step: Some(1.0),
+        ..SimConfig::default()
+    })
+}
+
+#[test]
+fn test_capture_initial_state() {
+    let mut backend = sim();
+    backend.acquire_fan_control().unwrap();
+    backend.set_fan_speed(55).unwrap();
+
+    assert_eq!(
+        InitialFanState::capture(&mut backend),
+        InitialFanState {
+            manual_control: Some(true),
+            fan_speed: 55,
+            fan_speeds: Vec::new(),
+            pwm_enable: None,
+        }
+    );
+    assert_eq!(backend.elapsed, 0.0, "The capture doesn't step the model");
+}
+
+// under manual control, but every read fails
+struct UnreadableBackend;
+
+impl GpuBackend for UnreadableBackend {
+    fn name(&self) -> &'static str {
+        "unreadable"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        Err("no reading".into())
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        Some(true)
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, _speed: u64) -> Result<(), Box<dyn Error>> {
+        panic!("A failed startup read must not be restored as 0 %");
+    }
+}
+
+#[test]
+fn test_capture_failed_read() {
+    let mut backend = UnreadableBackend;
+    let initial = InitialFanState::capture(&mut backend);
+    assert_eq!(initial.manual_control, None);
+    backend::apply_exit_mode(&mut backend, ExitMode::Restore, 100, &initial).unwrap();
+}
+
+#[test]
+fn test_apply_exit_mode() {
+    let manual_at_55 = InitialFanState {
+        manual_control: Some(true),
+        fan_speed: 55,
+        fan_speeds: Vec::new(),
+        pwm_enable: None,
+    };
+    let driver_auto = InitialFanState {
+        manual_control: Some(false),
+        ..InitialFanState::default()
+    };
+    let cases = vec![
+        (ExitMode::Auto, &manual_at_55, false, 80),
+        (ExitMode::Restore, &manual_at_55, true, 55),
+        (ExitMode::Restore, &driver_auto, false, 80),
+        (ExitMode::Speed, &driver_auto, true, 100),
+    ];
+
+    for (mode, initial, manual, speed) in cases {
+        let mut backend = sim();
+        backend.acquire_fan_control().unwrap();
+        backend.set_fan_speed(80).unwrap();
+
+        backend::apply_exit_mode(&mut backend, mode, 120, initial).unwrap();
+        assert_eq!(backend.get_fan_control(), Some(manual), "{:?}", mode);
+        assert_eq!(
+            backend.get_snapshot().unwrap().fan_speed,
+            speed,
+            "{:?}",
+            mode
+        );
+    }
+}
@@ -1,101 +1,577 @@
-use nix::unistd::{getuid, Uid};
-use std::process::{Command, Stdio};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::{getuid, Pid, Uid};
+use std::fmt;
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-pub fn get_gpu_temp(gpu_id: &u8) -> u64 {
-    let output = Command::new("nvidia-smi")
-        .args([
-            format!("--id={}", gpu_id).as_str(),
-            "--query-gpu=temperature.gpu",
-            "--format=csv,noheader",
-        ])
-        .output()
-        .expect("Failed to execute nvidia-smi");
+use crate::backend::GpuSnapshot;
+use crate::config::{Config, Escalation};
+
+pub const SNAPSHOT_FIELDS: [&str; 8] = [
+    "temperature.gpu",
+    "fan.speed",
+    "power.draw",
+    "utilization.gpu",
+    "clocks.gr",
+    "clocks.mem",
+    "clocks_throttle_reasons.active",
+    "temperature.memory",
+];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
 
-    let temp_str = String::from_utf8_lossy(&output.stdout);
+static TIMEOUTS: AtomicU64 = AtomicU64::new(0);
 
-    temp_str.trim().parse::<u64>().unwrap_or(0).clamp(0, 200)
+/// Number of subprocesses killed for exceeding their timeout since startup
+pub fn timeout_count() -> u64 {
+    TIMEOUTS.load(Ordering::SeqCst)
 }
 
-pub fn get_fan_speed(gpu_id: &u8) -> u64 {
-    let output = Command::new("nvidia-smi")
-        .args([
-            format!("--id={}", gpu_id).as_str(),
-            "--query-gpu=fan.speed",
-            "--format=csv,noheader",
-        ])
-        .output()
-        .expect("Failed to execute nvidia-smi");
+/// Counts a timeout raised outside `run_command`, e.g. a stalled sampling stream
+pub fn record_timeout() {
+    TIMEOUTS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    MissingBinary(String),
+    PermissionDenied(String, String),
+    PasswordRequired(String),
+    NoDisplay(String),
+    NonZeroExit(String, Option<i32>, String),
+    Spawn(String, String),
+    Timeout(String, Duration),
+    Unparseable(&'static str, String),
+    Unsupported(&'static str, String),
+    UnknownFans(u8),
+}
 
-    let _speed_str = String::from_utf8_lossy(&output.stdout);
-    let speed_str = _speed_str.trim().replace(" %", "");
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::MissingBinary(program) => {
+                write!(f, "'{}' was not found in PATH", program)
+            }
+            CommandError::PermissionDenied(program, msg) => {
+                write!(f, "Permission denied running '{}': {}", program, msg)
+            }
+            CommandError::PasswordRequired(tool) => write!(
+                f,
+                "'{}' needs a password, allow nvidia-settings without one or use the helper",
+                tool
+            ),
+            CommandError::NoDisplay(display) => write!(
+                f,
+                "nvidia-settings can't reach X display '{}', set 'display' and 'xauthority' in the config",
+                display
+            ),
+            CommandError::NonZeroExit(program, Some(code), stderr) => {
+                write!(f, "'{}' exited with code {}: {}", program, code, stderr)
+            }
+            CommandError::NonZeroExit(program, None, stderr) => {
+                write!(f, "'{}' was killed by a signal: {}", program, stderr)
+            }
+            CommandError::Spawn(program, msg) => write!(f, "Failed to run '{}': {}", program, msg),
+            CommandError::Timeout(program, timeout) => {
+                write!(
+                    f,
+                    "'{}' did not finish within {:?} and was killed",
+                    program, timeout
+                )
+            }
+            CommandError::Unparseable(field, value) => {
+                write!(f, "Could not parse {} from '{}'", field, value)
+            }
+            CommandError::Unsupported(field, value) => {
+                write!(f, "The GPU reports {} as {}", field, value)
+            }
+            CommandError::UnknownFans(gpu) => write!(
+                f,
+                "nvidia-settings lists no fans for [gpu:{}], set 'settings_fan_targets' for it",
+                gpu
+            ),
+        }
+    }
+}
+impl std::error::Error for CommandError {}
 
-    speed_str.parse::<u64>().unwrap_or(0).clamp(0, 100)
+fn looks_like_permission_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    [
+        "permission denied",
+        "not permitted",
+        "insufficient permissions",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
 }
 
-pub fn set_fan_control(gpu_id: &u8, mode: u8) -> Result<(), Box<dyn std::error::Error>> {
-    let is_root = Uid::is_root(getuid());
+fn looks_like_password_prompt(stderr: &str) -> bool {
+    // sudo -n, doas -n, pkexec without an agent and run0 --no-ask-password respectively
+    let stderr = stderr.to_lowercase();
+    [
+        "a password is required",
+        "authentication required",
+        "not authorized",
+        "interactive authentication required",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
+}
 
-    let mut command = if is_root {
-        Command::new("nvidia-settings")
-    } else {
-        let mut cmd = Command::new("sudo");
-        cmd.arg("nvidia-settings");
-        cmd
-    };
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
 
-    let output = command
-        .args([
-            "-c",
-            gpu_id.to_string().as_str(),
-            "-a",
-            format!("GPUFanControlState={}", mode).as_str(),
-        ])
-        .stdin(Stdio::piped())
+/// Runs `command` to completion, turning spawn failures, non-zero exits and
+/// runs longer than `timeout` into `CommandError`s. A timed out child is killed.
+pub fn run_command(command: &mut Command, timeout: Duration) -> Result<Output, CommandError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()?;
+        // its own process group, so a timeout also reaches what an escalation tool started
+        .process_group(0)
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CommandError::MissingBinary(program.clone()),
+            io::ErrorKind::PermissionDenied => {
+                CommandError::PermissionDenied(program.clone(), e.to_string())
+            }
+            _ => CommandError::Spawn(program.clone(), e.to_string()),
+        })?;
+
+    // nothing is ever written to the child, close stdin so it can't wait on it
+    drop(child.stdin.take());
+    // read both pipes while waiting so a chatty child can't block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                if killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL).is_err() {
+                    let _ = child.kill();
+                }
+                let _ = child.wait();
+                record_timeout();
+                return Err(CommandError::Timeout(program, timeout));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(CommandError::Spawn(program, e.to_string())),
+        }
+    };
 
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
     if output.status.success() {
-        Ok(())
+        return Ok(output);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if looks_like_password_prompt(&stderr) {
+        Err(CommandError::PasswordRequired(program))
+    } else if looks_like_permission_error(&stderr) {
+        Err(CommandError::PermissionDenied(program, stderr))
     } else {
-        Err(format!(
-            "Failed to execute nvidia-settings: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )
-        .into())
+        Err(CommandError::NonZeroExit(
+            program,
+            output.status.code(),
+            stderr,
+        ))
     }
 }
 
-pub fn set_fan_speed(gpu_id: &u8, speed: u64) -> Result<(), Box<dyn std::error::Error>> {
-    let is_root = Uid::is_root(getuid());
+fn parse_field(field: Option<&str>) -> Option<&str> {
+    // nvidia-smi reports unsupported values as "[N/A]" or "[Not Supported]"
+    field
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty() && !f.starts_with('['))
+}
 
-    let mut command = if is_root {
-        Command::new("nvidia-settings")
-    } else {
-        Command::new("sudo")
+fn parse_required<T: std::str::FromStr>(
+    name: &'static str,
+    field: Option<&str>,
+) -> Result<T, CommandError> {
+    let value = field.map(|f| f.trim()).unwrap_or("");
+    if value.starts_with('[') {
+        return Err(CommandError::Unsupported(name, value.to_string()));
+    }
+    value
+        .parse::<T>()
+        .map_err(|_| CommandError::Unparseable(name, value.to_string()))
+}
+
+/// Parses one `--format=csv,noheader,nounits` line of the snapshot query
+pub fn parse_snapshot(line: &str) -> Result<GpuSnapshot, CommandError> {
+    let mut fields = line.trim().split(',');
+    // without a temperature there is nothing to control on, the rest is informational
+    let temp = parse_required::<u64>(SNAPSHOT_FIELDS[0], fields.next())?;
+    let fan_speed = parse_field(fields.next()).and_then(|f| f.parse::<u64>().ok());
+    let power_draw = parse_field(fields.next()).and_then(|f| f.parse::<f64>().ok());
+    let utilization = parse_field(fields.next()).and_then(|f| f.parse::<u64>().ok());
+    let graphics_clock = parse_field(fields.next()).and_then(|f| f.parse::<u64>().ok());
+    let memory_clock = parse_field(fields.next()).and_then(|f| f.parse::<u64>().ok());
+    let throttle_reasons = parse_field(fields.next())
+        .and_then(|f| u64::from_str_radix(f.trim_start_matches("0x"), 16).ok());
+    let memory_temp = parse_field(fields.next()).and_then(|f| f.parse::<u64>().ok());
+
+    Ok(GpuSnapshot {
+        temp: temp.clamp(0, 200),
+        // nvidia-smi has no hotspot query
+        hotspot_temp: None,
+        memory_temp,
+        fan_speed: fan_speed.unwrap_or(0).clamp(0, 100),
+        fan_speeds: Vec::new(),
+        power_draw,
+        utilization,
+        graphics_clock,
+        memory_clock,
+        throttle_reasons,
+    })
+}
+
+pub fn get_gpu_snapshot(gpu_id: &u8, timeout: Duration) -> Result<GpuSnapshot, CommandError> {
+    let output = run_command(
+        Command::new("nvidia-smi").args([
+            format!("--id={}", gpu_id).as_str(),
+            format!("--query-gpu={}", SNAPSHOT_FIELDS.join(",")).as_str(),
+            "--format=csv,noheader,nounits",
+        ]),
+        timeout,
+    )?;
+
+    parse_snapshot(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Counts the `[fan:N]` targets listed by `nvidia-settings -q fans`
+pub fn parse_fan_count(output: &str) -> u64 {
+    let count = output.lines().filter(|line| line.contains("[fan:")).count() as u64;
+    count.max(1)
+}
+
+/// How nvidia-settings is launched and which targets it addresses
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsContext {
+    pub escalation: Escalation,
+    /// X display passed as `--ctrl-display`, the environment's `DISPLAY` when unset
+    pub display: Option<String>,
+    pub xauthority: Option<String>,
+    /// `[gpu:N]` index of the controlled GPU
+    pub gpu_target: u8,
+    /// `[fan:N]` index of each of the GPU's fans, looked up at startup when not configured;
+    /// empty to use 0..fan_count, which only a single-GPU machine may do
+    pub fan_targets: Vec<u64>,
+    pub timeout: Duration,
+}
+
+impl SettingsContext {
+    pub fn from_config(config: &Config) -> Self {
+        SettingsContext {
+            escalation: config.escalation,
+            display: config.display.clone(),
+            xauthority: config.xauthority.clone(),
+            gpu_target: config.settings_gpu_target.unwrap_or(config.gpu_id),
+            fan_targets: config.settings_fan_targets.clone(),
+            timeout: Duration::from_secs(config.command_timeout),
+        }
+    }
+
+    pub fn fan_target(&self, fan: u64) -> u64 {
+        self.fan_targets.get(fan as usize).copied().unwrap_or(fan)
+    }
+
+    /// Builds an nvidia-settings invocation, `escalate` is only needed for writes
+    pub fn command(&self, escalate: bool) -> Command {
+        let escalation = if escalate {
+            self.escalation
+        } else {
+            Escalation::None
+        };
+        let keep_env: Vec<(&str, &str)> = match &self.xauthority {
+            Some(xauthority) => vec![("XAUTHORITY", xauthority)],
+            None => Vec::new(),
+        };
+
+        let mut command = build_escalated(
+            escalation,
+            "nvidia-settings",
+            &keep_env,
+            Uid::is_root(getuid()),
+        );
+        if let Some(display) = &self.display {
+            // an argument survives escalation tools that reset the environment
+            command.arg(format!("--ctrl-display={}", display));
+        }
+        if let Some(xauthority) = &self.xauthority {
+            command.env("XAUTHORITY", xauthority);
+        }
+        command.stdin(Stdio::null());
+        command
+    }
+}
+
+/// Builds `program` behind the escalation tool, `is_root` skips escalation entirely.
+/// `keep_env` holds variables the tool should pass through, pkexec clears the environment
+/// so they're set again with `env` behind it. doas has no flag for it, only a `keepenv` or
+/// `setenv { ... }` rule in doas.conf passes them on.
+pub fn build_escalated(
+    escalation: Escalation,
+    program: &str,
+    keep_env: &[(&str, &str)],
+    is_root: bool,
+) -> Command {
+    // every tool is run non-interactively, a prompt would hang the control loop
+    let (tool, mut flags): (&str, Vec<String>) = match escalation {
+        _ if is_root => return Command::new(program),
+        Escalation::None => return Command::new(program),
+        Escalation::Sudo => ("sudo", vec!["-n".to_string()]),
+        Escalation::Doas => ("doas", vec!["-n".to_string()]),
+        Escalation::Pkexec => ("pkexec", vec!["--disable-internal-agent".to_string()]),
+        Escalation::Run0 => ("run0", vec!["--no-ask-password".to_string()]),
     };
+    let names: Vec<&str> = keep_env.iter().map(|(name, _)| *name).collect();
+    match escalation {
+        Escalation::Sudo if !names.is_empty() => {
+            flags.push(format!("--preserve-env={}", names.join(",")))
+        }
+        Escalation::Run0 => flags.extend(names.iter().map(|name| format!("--setenv={}", name))),
+        Escalation::Pkexec if !keep_env.is_empty() => {
+            flags.push("env".to_string());
+            flags.extend(
+                keep_env
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value)),
+            );
+        }
+        _ => {}
+    }
 
-    let output = command
-        .args([
-            "-c",
-            gpu_id.to_string().as_str(),
-            "-a",
-            "GPUFanControlState=1",
-            "-a",
-            &format!("GPUTargetFanSpeed={}", speed),
-        ])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()?;
+    let mut command = Command::new(tool);
+    command.args(flags).arg(program);
+    command
+}
 
-    if output.status.success() {
-        Ok(())
+fn looks_like_display_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    [
+        "unable to init server",
+        "cannot open display",
+        "control display is undefined",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
+}
+
+/// Turns an nvidia-settings failure into `NoDisplay` when it couldn't open the display
+fn display_error(ctx: &SettingsContext, error: CommandError) -> CommandError {
+    match error {
+        CommandError::NonZeroExit(_, _, stderr) if looks_like_display_error(&stderr) => {
+            CommandError::NoDisplay(
+                ctx.display
+                    .clone()
+                    .or_else(|| std::env::var("DISPLAY").ok())
+                    .unwrap_or_else(|| "<unset>".to_string()),
+            )
+        }
+        error => error,
+    }
+}
+
+/// Turns the result of a query into `NoDisplay` when nvidia-settings couldn't open the display
+pub fn check_display_result(
+    ctx: &SettingsContext,
+    result: Result<Output, CommandError>,
+) -> Result<(), CommandError> {
+    result.map(|_| ()).map_err(|e| display_error(ctx, e))
+}
+
+/// Queries the GPU target once so a missing X display is reported before taking control
+pub fn check_display(ctx: &SettingsContext) -> Result<(), CommandError> {
+    let mut command = ctx.command(false);
+    command.args([
+        "-t",
+        "-q",
+        &format!("[gpu:{}]/GPUFanControlState", ctx.gpu_target),
+    ]);
+    check_display_result(ctx, run_command(&mut command, ctx.timeout))
+}
+
+/// Lists each `[gpu:N]` of verbose `nvidia-settings -q gpus` output with the `[fan:N]`
+/// targets it uses
+pub fn parse_gpu_fans(output: &str) -> Vec<(u8, Vec<u64>)> {
+    fn indices<'a>(line: &'a str, prefix: &'a str) -> impl Iterator<Item = u64> + 'a {
+        line.split(prefix)
+            .skip(1)
+            .filter_map(|rest| rest.split(']').next()?.parse().ok())
+    }
+
+    let mut gpus: Vec<(u8, Vec<u64>)> = Vec::new();
+    for line in output.lines() {
+        // a GPU's own line starts its block, its fans are listed under it
+        if let Some(gpu) = indices(line, "[gpu:").next() {
+            gpus.push((gpu as u8, Vec::new()));
+        } else if let Some((_, fans)) = gpus.last_mut() {
+            fans.extend(indices(line, "[fan:"));
+        }
+    }
+    gpus
+}
+
+/// Finds the `[fan:N]` targets of the GPU `ctx` addresses, empty when it is the only GPU
+/// so the machine-wide fan numbering is its own
+pub fn get_gpu_fan_targets(ctx: &SettingsContext) -> Result<Vec<u64>, CommandError> {
+    let mut command = ctx.command(false);
+    command.args(["-q", "gpus", "--verbose=all"]);
+    let output = run_command(&mut command, ctx.timeout).map_err(|e| display_error(ctx, e))?;
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let gpus = parse_gpu_fans(&output);
+    match gpus.iter().find(|(gpu, _)| *gpu == ctx.gpu_target) {
+        Some((_, fans)) if !fans.is_empty() => Ok(fans.clone()),
+        _ if gpus.len() <= 1 => Ok(Vec::new()),
+        _ => Err(CommandError::UnknownFans(ctx.gpu_target)),
+    }
+}
+
+pub fn get_fan_count(ctx: &SettingsContext) -> u64 {
+    if !ctx.fan_targets.is_empty() {
+        return ctx.fan_targets.len() as u64;
+    }
+
+    let mut command = ctx.command(false);
+    command.args(["-q", "fans"]);
+    match run_command(&mut command, ctx.timeout) {
+        Ok(output) => parse_fan_count(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => 1,
+    }
+}
+
+/// Parses terse output of a per-fan `attribute`, one value per queried fan
+fn parse_fan_values(attribute: &'static str, output: &str) -> Result<Vec<u64>, CommandError> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse::<u64>()
+                .map_err(|_| CommandError::Unparseable(attribute, line.to_string()))
+        })
+        .collect()
+}
+
+/// Parses terse `GPUCurrentFanSpeedRPM` output, one value per queried fan
+pub fn parse_fan_rpms(output: &str) -> Result<Vec<u64>, CommandError> {
+    parse_fan_values("GPUCurrentFanSpeedRPM", output)
+}
+
+/// Parses terse `GPUCurrentFanSpeed` output, one percentage per queried fan
+pub fn parse_fan_speeds(output: &str) -> Result<Vec<u64>, CommandError> {
+    Ok(parse_fan_values("GPUCurrentFanSpeed", output)?
+        .into_iter()
+        .map(|speed| speed.clamp(0, 100))
+        .collect())
+}
+
+/// Queries `attribute` of each of the GPU's fans in one nvidia-settings call
+fn query_fans(
+    ctx: &SettingsContext,
+    fan_count: u64,
+    attribute: &str,
+) -> Result<String, CommandError> {
+    let mut command = ctx.command(false);
+    command.arg("-t");
+    for fan in 0..fan_count {
+        command.args([
+            "-q",
+            &format!("[fan:{}]/{}", ctx.fan_target(fan), attribute),
+        ]);
+    }
+
+    let output = run_command(&mut command, ctx.timeout)?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn get_fan_rpms(ctx: &SettingsContext, fan_count: u64) -> Result<Vec<u64>, CommandError> {
+    parse_fan_rpms(&query_fans(ctx, fan_count, "GPUCurrentFanSpeedRPM")?)
+}
+
+pub fn get_fan_speeds(ctx: &SettingsContext, fan_count: u64) -> Result<Vec<u64>, CommandError> {
+    parse_fan_speeds(&query_fans(ctx, fan_count, "GPUCurrentFanSpeed")?)
+}
+
+/// Returns whether the GPU's fans are under manual control
+pub fn get_fan_control(ctx: &SettingsContext) -> Result<bool, CommandError> {
+    let mut command = ctx.command(false);
+    command.args([
+        "-t",
+        "-q",
+        &format!("[gpu:{}]/GPUFanControlState", ctx.gpu_target),
+    ]);
+
+    let output = run_command(&mut command, ctx.timeout)?;
+    let state: u8 = parse_required(
+        "GPUFanControlState",
+        Some(&String::from_utf8_lossy(&output.stdout)),
+    )?;
+    Ok(state == 1)
+}
+
+pub fn set_fan_control(ctx: &SettingsContext, mode: u8) -> Result<(), CommandError> {
+    let mut command = ctx.command(true);
+    command.args([
+        "-a",
+        &format!("[gpu:{}]/GPUFanControlState={}", ctx.gpu_target, mode),
+    ]);
+
+    run_command(&mut command, ctx.timeout)?;
+    Ok(())
+}
+
+/// Sets every fan of the GPU, or every fan nvidia-settings knows on a single-GPU machine
+/// where no fan targets were found
+pub fn set_fan_speed(ctx: &SettingsContext, speed: u64) -> Result<(), CommandError> {
+    let mut command = ctx.command(true);
+    command.args([
+        "-a",
+        &format!("[gpu:{}]/GPUFanControlState=1", ctx.gpu_target),
+    ]);
+    if ctx.fan_targets.is_empty() {
+        command.args(["-a", &format!("GPUTargetFanSpeed={}", speed)]);
     } else {
-        Err(format!(
-            "Failed to execute nvidia-settings: {}",
-            String::from_utf8_lossy(&output.stderr)
-        )
-        .into())
+        for fan in &ctx.fan_targets {
+            command.args(["-a", &format!("[fan:{}]/GPUTargetFanSpeed={}", fan, speed)]);
+        }
     }
+
+    run_command(&mut command, ctx.timeout)?;
+    Ok(())
+}
+
+/// Sets the GPU's `fan`-th fan, mapped through `fan_targets`
+pub fn set_fan_speed_of(ctx: &SettingsContext, fan: u64, speed: u64) -> Result<(), CommandError> {
+    let mut command = ctx.command(true);
+    command.args([
+        "-a",
+        &format!("[gpu:{}]/GPUFanControlState=1", ctx.gpu_target),
+        "-a",
+        &format!("[fan:{}]/GPUTargetFanSpeed={}", ctx.fan_target(fan), speed),
+    ]);
+
+    run_command(&mut command, ctx.timeout)?;
+    Ok(())
 }
@@ -0,0 +1,392 @@
+use crate::backend::GpuSnapshot;
+use std::ffi::OsStr;
+use std::fs;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+use crate::commands::{self, CommandError, SettingsContext};
+use crate::config::{Config, Escalation};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn test_parse_snapshot() {
+    let snapshot =
+        commands::parse_snapshot("64, 55, 231.45, 98, 1935, 10501, 0x0000000000000004, 88\n")
+            .unwrap();
+    assert_eq!(
+        snapshot,
+        GpuSnapshot {
+            temp: 64,
+            hotspot_temp: None,
+            memory_temp: Some(88),
+            fan_speed: 55,
+            fan_speeds: Vec::new(),
+            power_draw: Some(231.45),
+            utilization: Some(98),
+            graphics_clock: Some(1935),
+            memory_clock: Some(10501),
+            throttle_reasons: Some(0x4),
+        }
+    );
+}
+
+#[test]
+fn test_parse_snapshot_unsupported_fields() {
+    let snapshot =
+        commands::parse_snapshot("41, [N/A], [Not Supported], 0, 210, 405, [N/A]").unwrap();
+    assert_eq!(snapshot.temp, 41);
+    assert_eq!(snapshot.fan_speed, 0);
+    assert_eq!(snapshot.power_draw, None);
+    assert_eq!(snapshot.utilization, Some(0));
+    assert_eq!(snapshot.throttle_reasons, None);
+    assert_eq!(snapshot.memory_temp, None);
+
+    // a truncated line leaves the remaining fields empty
+    let snapshot = commands::parse_snapshot("45, 30").unwrap();
+    assert_eq!((snapshot.temp, snapshot.fan_speed), (45, 30));
+    assert_eq!(snapshot.graphics_clock, None);
+}
+
+#[test]
+fn test_parse_fan_count() {
+    let output = "
+  2 Fans on desktop:0
+
+    [0] desktop:0[fan:0] (Fan 0)
+    [1] desktop:0[fan:1] (Fan 1)
+";
+    assert_eq!(commands::parse_fan_count(output), 2);
+    assert_eq!(
+        commands::parse_fan_count(""),
+        1,
+        "Assume one fan when unknown"
+    );
+}
+
+#[test]
+fn test_parse_gpu_fans() {
+    let output = "
+  2 GPUs on desktop:0
+
+    [0] desktop:0[gpu:0] (NVIDIA GeForce RTX 3080)
+
+      Has the following names:
+        GPU-0
+
+      Is driving the following X screen:
+           desktop:0[screen:0] (Screen 0)
+
+      Uses the following fans:
+           desktop:0[fan:0] (Fan 0)
+           desktop:0[fan:1] (Fan 1)
+
+    [1] desktop:0[gpu:1] (NVIDIA GeForce RTX 3060)
+
+      Uses the following fan:
+           desktop:0[fan:2] (Fan 2)
+";
+    assert_eq!(
+        commands::parse_gpu_fans(output),
+        vec![(0, vec![0, 1]), (1, vec![2])]
+    );
+    assert_eq!(commands::parse_gpu_fans(""), vec![]);
+}
+
+#[test]
+fn test_parse_fan_rpms() {
+    assert_eq!(commands::parse_fan_rpms("1650\n0\n"), Ok(vec![1650, 0]));
+    assert_eq!(commands::parse_fan_rpms(""), Ok(vec![]));
+    assert_eq!(
+        commands::parse_fan_rpms("1650\nN/A\n"),
+        Err(CommandError::Unparseable(
+            "GPUCurrentFanSpeedRPM",
+            "N/A".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_parse_fan_speeds() {
+    assert_eq!(commands::parse_fan_speeds("45\n60\n"), Ok(vec![45, 60]));
+    assert_eq!(commands::parse_fan_speeds("120\n"), Ok(vec![100]));
+    assert_eq!(
+        commands::parse_fan_speeds("45\n\n"),
+        Ok(vec![45]),
+        "Blank lines are skipped"
+    );
+    assert_eq!(
+        commands::parse_fan_speeds("N/A\n"),
+        Err(CommandError::Unparseable(
+            "GPUCurrentFanSpeed",
+            "N/A".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_parse_snapshot_errors() {
+    assert_eq!(
+        commands::parse_snapshot("[N/A], 40, 100.0"),
+        Err(CommandError::Unsupported(
+            "temperature.gpu",
+            "[N/A]".to_string()
+        ))
+    );
+    assert_eq!(
+        commands::parse_snapshot("Failed to initialize NVML: Driver/library version mismatch"),
+        Err(CommandError::Unparseable(
+            "temperature.gpu",
+            "Failed to initialize NVML: Driver/library version mismatch".to_string()
+        ))
+    );
+    assert!(matches!(
+        commands::parse_snapshot(""),
+        Err(CommandError::Unparseable(_, _))
+    ));
+}
+
+#[test]
+fn test_run_command_errors() {
+    assert_eq!(
+        commands::run_command(&mut Command::new("/nonexistent/nvidia-smi"), TIMEOUT),
+        Err(CommandError::MissingBinary(
+            "/nonexistent/nvidia-smi".to_string()
+        ))
+    );
+
+    let result = commands::run_command(
+        Command::new("sh").args(["-c", "echo boom >&2; exit 3"]),
+        TIMEOUT,
+    );
+    assert_eq!(
+        result,
+        Err(CommandError::NonZeroExit(
+            "sh".to_string(),
+            Some(3),
+            "boom".to_string()
+        ))
+    );
+
+    let result = commands::run_command(
+        Command::new("sh").args(["-c", "echo 'ERROR: Insufficient Permissions' >&2; exit 1"]),
+        TIMEOUT,
+    );
+    assert!(matches!(result, Err(CommandError::PermissionDenied(_, _))));
+
+    let output =
+        commands::run_command(Command::new("sh").args(["-c", "echo 61"]), TIMEOUT).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "61");
+}
+
+#[test]
+fn test_run_command_timeout() {
+    let timeouts = commands::timeout_count();
+    let started = Instant::now();
+    let result = commands::run_command(
+        Command::new("sh").args(["-c", "exec sleep 5"]),
+        Duration::from_millis(100),
+    );
+
+    assert_eq!(
+        result,
+        Err(CommandError::Timeout(
+            "sh".to_string(),
+            Duration::from_millis(100)
+        ))
+    );
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "The child should be killed"
+    );
+    assert!(commands::timeout_count() > timeouts);
+}
+
+// a killed process lingers as a zombie until something reaps it
+fn is_running(pid: &str) -> bool {
+    fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok_and(|stat| {
+        !stat
+            .rsplit(')')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .starts_with('Z')
+    })
+}
+
+#[test]
+fn test_run_command_timeout_kills_grandchildren() {
+    let temp_dir = TempDir::new().unwrap();
+    let pid_file = temp_dir.path().join("pid");
+    // sh waits on sleep like an escalation tool waits on the program it started
+    let script = format!("sleep 30 & echo $! > {}; wait", pid_file.display());
+    let result = commands::run_command(
+        Command::new("sh").args(["-c", &script]),
+        Duration::from_millis(200),
+    );
+    assert!(matches!(result, Err(CommandError::Timeout(..))));
+
+    let pid = fs::read_to_string(&pid_file).unwrap().trim().to_string();
+    let started = Instant::now();
+    while is_running(&pid) && started.elapsed() < Duration::from_secs(2) {
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(!is_running(&pid), "The grandchild should be killed too");
+}
+
+#[test]
+fn test_build_escalated() {
+    let cases: Vec<(Escalation, &str, Vec<&str>)> = vec![
+        (Escalation::None, "nvidia-settings", vec![]),
+        (Escalation::Sudo, "sudo", vec!["-n", "nvidia-settings"]),
+        (Escalation::Doas, "doas", vec!["-n", "nvidia-settings"]),
+        (
+            Escalation::Pkexec,
+            "pkexec",
+            vec!["--disable-internal-agent", "nvidia-settings"],
+        ),
+        (
+            Escalation::Run0,
+            "run0",
+            vec!["--no-ask-password", "nvidia-settings"],
+        ),
+    ];
+    for (escalation, program, args) in cases {
+        let command = commands::build_escalated(escalation, "nvidia-settings", &[], false);
+        assert_eq!(command.get_program(), program, "{:?}", escalation);
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            args,
+            "{:?}",
+            escalation
+        );
+    }
+
+    // root never needs a wrapper
+    let command = commands::build_escalated(Escalation::Sudo, "nvidia-settings", &[], true);
+    assert_eq!(command.get_program(), "nvidia-settings");
+    assert_eq!(command.get_args().count(), 0);
+
+    let keep_env = [("XAUTHORITY", "/run/user/1000/xauth")];
+    let cases: Vec<(Escalation, Vec<&str>)> = vec![
+        (
+            Escalation::Sudo,
+            vec!["-n", "--preserve-env=XAUTHORITY", "nvidia-settings"],
+        ),
+        (Escalation::Doas, vec!["-n", "nvidia-settings"]),
+        (
+            Escalation::Pkexec,
+            vec![
+                "--disable-internal-agent",
+                "env",
+                "XAUTHORITY=/run/user/1000/xauth",
+                "nvidia-settings",
+            ],
+        ),
+        (
+            Escalation::Run0,
+            vec![
+                "--no-ask-password",
+                "--setenv=XAUTHORITY",
+                "nvidia-settings",
+            ],
+        ),
+    ];
+    for (escalation, args) in cases {
+        let command = commands::build_escalated(escalation, "nvidia-settings", &keep_env, false);
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            args,
+            "{:?}",
+            escalation
+        );
+    }
+}
+
+fn settings() -> SettingsContext {
+    SettingsContext {
+        escalation: Escalation::Sudo,
+        display: Some(":1".to_string()),
+        xauthority: Some("/run/user/1000/xauth".to_string()),
+        gpu_target: 1,
+        fan_targets: vec![2, 3],
+        timeout: TIMEOUT,
+    }
+}
+
+#[test]
+fn test_settings_command() {
+    let ctx = settings();
+    let command = ctx.command(false);
+    assert_eq!(command.get_program(), "nvidia-settings");
+    assert_eq!(
+        command.get_args().collect::<Vec<_>>(),
+        vec!["--ctrl-display=:1"]
+    );
+    assert!(command.get_envs().any(
+        |(key, value)| key == "XAUTHORITY" && value == Some(OsStr::new("/run/user/1000/xauth"))
+    ));
+
+    // local fan indices map onto the GPU's nvidia-settings fans
+    assert_eq!(ctx.fan_target(0), 2);
+    assert_eq!(ctx.fan_target(1), 3);
+    assert_eq!(ctx.fan_target(2), 2, "Unmapped fans keep their index");
+    assert_eq!(commands::get_fan_count(&ctx), 2);
+
+    let from_config = SettingsContext::from_config(&Config {
+        gpu_id: 1,
+        ..Config::default()
+    });
+    assert_eq!(from_config.gpu_target, 1);
+    assert_eq!(from_config.display, None);
+}
+
+#[test]
+fn test_check_display() {
+    let ctx = settings();
+    let stderrs = vec![
+        "ERROR: Unable to init server: Could not connect: Connection refused",
+        "ERROR: cannot open display ':1'",
+        "ERROR: The control display is undefined; please run with --display",
+    ];
+    for stderr in stderrs {
+        let script = format!("echo \"{}\" >&2; exit 1", stderr);
+        let result = commands::run_command(Command::new("sh").args(["-c", &script]), TIMEOUT);
+        assert_eq!(
+            commands::check_display_result(&ctx, result),
+            Err(CommandError::NoDisplay(":1".to_string())),
+            "{}",
+            stderr
+        );
+    }
+
+    let result = commands::run_command(Command::new("sh").args(["-c", "exit 2"]), TIMEOUT);
+    assert!(matches!(
+        commands::check_display_result(&ctx, result),
+        Err(CommandError::NonZeroExit(_, Some(2), _))
+    ));
+    let result = commands::run_command(Command::new("sh").args(["-c", "exit 0"]), TIMEOUT);
+    assert_eq!(commands::check_display_result(&ctx, result), Ok(()));
+}
+
+#[test]
+fn test_run_command_password_required() {
+    let prompts = vec![
+        "sudo: a password is required",
+        "doas: Authentication required",
+        "Error executing command as another user: Not authorized",
+        "Interactive authentication required.",
+    ];
+    for prompt in prompts {
+        let script = format!("echo '{}' >&2; exit 1", prompt);
+        let result = commands::run_command(Command::new("sh").args(["-c", &script]), TIMEOUT);
+        assert_eq!(
+            result,
+            Err(CommandError::PasswordRequired("sh".to_string())),
+            "{}",
+            prompt
+        );
+    }
+}
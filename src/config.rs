@@ -6,9 +6,402 @@ use std::fs::{File, OpenOptions};
 use std::io::{ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Nvidia,
+    #[serde(rename = "nvidia-stream")]
+    NvidiaStream,
+    Nvml,
+    Hwmon,
+    Sim,
+    Replay,
+}
+
+/// How nvidia-settings is elevated when not running as root
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Escalation {
+    None,
+    #[default]
+    Sudo,
+    Doas,
+    Pkexec,
+    Run0,
+}
+
+/// What to do with the fans once GPU reads keep failing
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailsafeMode {
+    /// Command `failsafe_speed`
+    #[default]
+    Speed,
+    /// Hand the fans back to the driver's automatic control
+    Auto,
+}
+
+/// How the fans are left when the controller exits
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExitMode {
+    /// Hand the fans back to the driver's automatic control
+    #[default]
+    Auto,
+    /// Return to the control state and speeds found at startup
+    Restore,
+    /// Keep manual control at `exit_speed`
+    Speed,
+}
+
+/// How the fan speed is chosen
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlMode {
+    /// Follow the `temp_thresholds`/`fan_speeds` curve
+    #[default]
+    Curve,
+    /// Adjust the fans to hold `pid_target_temp`
+    Pid,
+}
+
+/// How the curve is read between `temp_thresholds`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// Hold each threshold's speed until the next threshold
+    Step,
+    /// Straight lines between the thresholds
+    Linear,
+    /// Monotone cubic (PCHIP), smooth through every threshold without overshooting
+    Pchip,
+}
+
+/// What to do once a fan reports 0 RPM for longer than `fan_stall_time`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StallAction {
+    /// Only log the stalled fan
+    #[default]
+    Alert,
+    /// Also apply the `failsafe_mode` policy until every fan spins again
+    Failsafe,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sensor {
+    #[default]
+    Core,
+    Hotspot,
+    Memory,
+}
+
+impl fmt::Display for Sensor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sensor::Core => write!(f, "core"),
+            Sensor::Hotspot => write!(f, "hotspot"),
+            Sensor::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// Either one sensor, or a list whose hottest available reading is used
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SensorSelection {
+    Single(Sensor),
+    Max(Vec<Sensor>),
+}
+
+impl Default for SensorSelection {
+    fn default() -> Self {
+        SensorSelection::Single(Sensor::Core)
+    }
+}
+
+impl SensorSelection {
+    pub fn sensors(&self) -> Vec<Sensor> {
+        match self {
+            SensorSelection::Single(sensor) => vec![*sensor],
+            SensorSelection::Max(sensors) => sensors.clone(),
+        }
+    }
+}
+
+/// How the readings of several `temp_sources` become one temperature
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TempCombine {
+    /// The hottest source
+    #[default]
+    Max,
+    /// The weighted average of every source
+    Weighted,
+}
+
+/// One sensor of one GPU feeding a shared fan
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TempSource {
+    pub gpu_id: u8,
+    #[serde(default)]
+    pub sensor: Sensor,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+/// Per-fan overrides, anything left unset follows the main curve
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct FanConfig {
+    pub index: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_thresholds: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speeds: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speed_floor: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speed_ceiling: Option<u64>,
+}
+
+/// One `[[gpu]]` section, anything left unset follows the top-level settings
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct GpuConfig {
+    pub gpu_id: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_gpu_target: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_fan_targets: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<SensorSelection>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_sources: Option<Vec<TempSource>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_combine: Option<TempCombine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_thresholds: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speeds: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speed_floor: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speed_ceiling: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hysteresis: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling_window_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_dwell_time: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ControlMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid_target_temp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<Interpolation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smooth_mode: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smooth_mode_incr_weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smooth_mode_decr_weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smooth_mode_max_fan_step: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fans: Option<Vec<FanConfig>>,
+}
+
+impl GpuConfig {
+    /// Builds the full config of this GPU on top of `base`
+    pub fn apply(&self, base: &Config) -> Config {
+        let base = base.clone();
+        Config {
+            gpu_id: self.gpu_id,
+            settings_gpu_target: self.settings_gpu_target.or(base.settings_gpu_target),
+            settings_fan_targets: self
+                .settings_fan_targets
+                .clone()
+                .unwrap_or(base.settings_fan_targets),
+            sensor: self.sensor.clone().unwrap_or(base.sensor),
+            temp_sources: self.temp_sources.clone().unwrap_or(base.temp_sources),
+            temp_combine: self.temp_combine.unwrap_or(base.temp_combine),
+            temp_thresholds: self.temp_thresholds.clone().unwrap_or(base.temp_thresholds),
+            fan_speeds: self.fan_speeds.clone().unwrap_or(base.fan_speeds),
+            fan_speed_floor: self.fan_speed_floor.unwrap_or(base.fan_speed_floor),
+            fan_speed_ceiling: self.fan_speed_ceiling.unwrap_or(base.fan_speed_ceiling),
+            hysteresis: self.hysteresis.unwrap_or(base.hysteresis),
+            sampling_window_size: self
+                .sampling_window_size
+                .unwrap_or(base.sampling_window_size),
+            fan_dwell_time: self.fan_dwell_time.unwrap_or(base.fan_dwell_time),
+            mode: self.mode.unwrap_or(base.mode),
+            pid_target_temp: self.pid_target_temp.unwrap_or(base.pid_target_temp),
+            interpolation: self.interpolation.or(base.interpolation),
+            smooth_mode: self.smooth_mode.unwrap_or(base.smooth_mode),
+            smooth_mode_incr_weight: self
+                .smooth_mode_incr_weight
+                .unwrap_or(base.smooth_mode_incr_weight),
+            smooth_mode_decr_weight: self
+                .smooth_mode_decr_weight
+                .unwrap_or(base.smooth_mode_decr_weight),
+            smooth_mode_max_fan_step: self
+                .smooth_mode_max_fan_step
+                .unwrap_or(base.smooth_mode_max_fan_step),
+            fans: self.fans.clone().unwrap_or(base.fans),
+            gpus: Vec::new(),
+            pwm_outputs: Vec::new(),
+            ..base
+        }
+    }
+}
+
+/// One `[[pwm_output]]` section, a hwmon PWM channel following a GPU's temperature
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct PwmOutputConfig {
+    /// `name` of the hwmon device, e.g. "nct6798"
+    pub hwmon: String,
+    /// N of the `pwmN` channel
+    pub channel: u8,
+    /// GPU whose temperature drives the channel, defaults to `gpu_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_id: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<SensorSelection>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_thresholds: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speeds: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speed_floor: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fan_speed_ceiling: Option<u64>,
+}
+
+impl PwmOutputConfig {
+    /// Builds the config of this output on top of the config of the GPU it follows
+    pub fn apply(&self, gpu: &Config) -> Config {
+        let gpu = gpu.clone();
+        Config {
+            sensor: self.sensor.clone().unwrap_or(gpu.sensor),
+            temp_thresholds: self.temp_thresholds.clone().unwrap_or(gpu.temp_thresholds),
+            fan_speeds: self.fan_speeds.clone().unwrap_or(gpu.fan_speeds),
+            fan_speed_floor: self.fan_speed_floor.unwrap_or(gpu.fan_speed_floor),
+            fan_speed_ceiling: self.fan_speed_ceiling.unwrap_or(gpu.fan_speed_ceiling),
+            // a PWM channel is a single fan
+            fans: Vec::new(),
+            pwm_outputs: Vec::new(),
+            ..gpu
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}/pwm{}", self.hwmon, self.channel)
+    }
+}
+
+/// Something with its own control loop: a GPU's fans, or a PWM output following a GPU
+#[derive(Debug, Clone)]
+pub struct ControlTarget {
+    pub config: Config,
+    pub pwm_output: Option<PwmOutputConfig>,
+}
+
+impl ControlTarget {
+    pub fn label(&self) -> String {
+        match &self.pwm_output {
+            Some(output) => output.label(),
+            None => format!("GPU {}", self.config.gpu_id),
+        }
+    }
+}
+
+/// Heat input for the simulated GPU, in watts
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HeatLoad {
+    Constant {
+        watts: f64,
+    },
+    /// Switches from `before` to `after` watts once `at` seconds have elapsed
+    Step {
+        before: f64,
+        after: f64,
+        at: f64,
+    },
+    /// Piecewise constant `[seconds, watts]` points, held until the next point
+    Script {
+        points: Vec<(f64, f64)>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SimConfig {
+    pub ambient_temp: f64,
+    pub initial_temp: f64,
+    /// Joules needed to raise the GPU by 1 C
+    pub heat_capacity: f64,
+    /// Watts shed per C above ambient with the fans stopped
+    pub idle_conductance: f64,
+    /// Additional watts shed per C above ambient with the fans at 100%
+    pub fan_conductance: f64,
+    pub heat_load: HeatLoad,
+    /// Simulated seconds per reading, uses wall-clock time when unset
+    pub time_step: Option<f64>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            ambient_temp: 30.0,
+            initial_temp: 30.0,
+            heat_capacity: 300.0,
+            idle_conductance: 2.0,
+            fan_conductance: 8.0,
+            heat_load: HeatLoad::Constant { watts: 200.0 },
+            time_step: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
+    #[serde(default)]
+    pub backend: BackendKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nvml_library_path: Option<String>,
+    #[serde(default = "default_sysfs_root")]
+    pub sysfs_root: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_trace: Option<String>,
+    #[serde(default)]
+    pub escalation: Escalation,
+    /// Socket of a root `--helper`, when set fan writes are sent there instead of sudo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helper_socket: Option<String>,
+    /// Users the `--helper` accepts requests from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub helper_allowed_users: Vec<String>,
+    /// X display nvidia-settings connects to, the environment's `DISPLAY` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xauthority: Option<String>,
+    /// nvidia-settings `[gpu:N]` index, defaults to `gpu_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_gpu_target: Option<u8>,
+    /// nvidia-settings `[fan:N]` indices of this GPU's fans, which are numbered across all GPUs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub settings_fan_targets: Vec<u64>,
     pub gpu_id: u8,
+    #[serde(default)]
+    pub sensor: SensorSelection,
+    /// Drives this GPU's fans from sensors of several GPUs instead of `sensor`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub temp_sources: Vec<TempSource>,
+    #[serde(default)]
+    pub temp_combine: TempCombine,
     pub temp_thresholds: Vec<u64>,
     pub fan_speeds: Vec<u64>,
     pub fan_speed_floor: u64,
@@ -16,11 +409,72 @@ pub struct Config {
     pub hysteresis: u64,
     pub sampling_window_size: usize,
     pub global_delay: u64,
+    /// Seconds a nvidia-smi/nvidia-settings call may take before it is killed
+    #[serde(default = "default_command_timeout")]
+    pub command_timeout: u64,
     pub fan_dwell_time: u64,
+    #[serde(default)]
+    pub mode: ControlMode,
+    /// Temperature the `pid` mode holds with the quietest fan speed it can
+    #[serde(default = "default_pid_target_temp")]
+    pub pid_target_temp: u64,
+    /// Percent of fan speed per degree above the target
+    #[serde(default = "default_pid_kp")]
+    pub pid_kp: f64,
+    /// Percent of fan speed per degree-second above the target
+    #[serde(default = "default_pid_ki")]
+    pub pid_ki: f64,
+    /// Percent of fan speed per degree per second of temperature rise
+    #[serde(default = "default_pid_kd")]
+    pub pid_kd: f64,
+    /// Share of the previous derivative kept each step (0 disables filtering, below 1)
+    #[serde(default = "default_pid_derivative_filter")]
+    pub pid_derivative_filter: f64,
+    /// Shape of the curve between thresholds, linear with `smooth_mode` and step without when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpolation: Option<Interpolation>,
     pub smooth_mode: bool,
     pub smooth_mode_incr_weight: f64,
     pub smooth_mode_decr_weight: f64,
     pub smooth_mode_max_fan_step: u64,
+    /// Consecutive failed reads before entering fail-safe
+    #[serde(default = "default_failsafe_after")]
+    pub failsafe_after: u64,
+    /// Consecutive good reads before leaving fail-safe
+    #[serde(default = "default_failsafe_recover_after")]
+    pub failsafe_recover_after: u64,
+    #[serde(default)]
+    pub failsafe_mode: FailsafeMode,
+    #[serde(default = "default_failsafe_speed")]
+    pub failsafe_speed: u64,
+    /// Seconds a fan may report 0 RPM while commanded above the floor, 0 disables the check
+    #[serde(default = "default_fan_stall_time")]
+    pub fan_stall_time: u64,
+    #[serde(default)]
+    pub fan_stall_action: StallAction,
+    /// Seconds between checks that manual control is still held, 0 disables them
+    #[serde(default = "default_control_check_interval")]
+    pub control_check_interval: u64,
+    /// How far the reported fan speed may drift from the commanded one, in percent
+    #[serde(default = "default_fan_speed_tolerance")]
+    pub fan_speed_tolerance: u64,
+    /// Consecutive failed fan writes before the controller gives up and exits
+    #[serde(default = "default_max_write_failures")]
+    pub max_write_failures: u64,
+    #[serde(default)]
+    pub exit_mode: ExitMode,
+    #[serde(default = "default_exit_speed")]
+    pub exit_speed: u64,
+    /// Controls several GPUs from one daemon, the top-level `gpu_id` is unused when set
+    #[serde(default, rename = "gpu", skip_serializing_if = "Vec::is_empty")]
+    pub gpus: Vec<GpuConfig>,
+    /// Extra hwmon PWM channels, such as case fans, each with its own curve
+    #[serde(default, rename = "pwm_output", skip_serializing_if = "Vec::is_empty")]
+    pub pwm_outputs: Vec<PwmOutputConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fans: Vec<FanConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sim: Option<SimConfig>,
 }
 
 #[derive(Debug)]
@@ -31,12 +485,93 @@ pub enum ConfigError {
     MissingConfigFile,
     InvalidDirectory,
     InvalidArrayFormat,
+    UnsortedCurve,
+    DuplicateGpu(u8),
+    InvalidPidSetting(&'static str),
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_sysfs_root() -> String {
+    "/sys".to_string()
+}
+
+fn default_command_timeout() -> u64 {
+    5
+}
+
+fn default_pid_target_temp() -> u64 {
+    70
+}
+
+fn default_pid_kp() -> f64 {
+    4.0
+}
+
+fn default_pid_ki() -> f64 {
+    0.2
+}
+
+fn default_pid_kd() -> f64 {
+    10.0
+}
+
+fn default_pid_derivative_filter() -> f64 {
+    0.7
+}
+
+fn default_failsafe_after() -> u64 {
+    3
+}
+
+fn default_failsafe_recover_after() -> u64 {
+    3
+}
+
+fn default_failsafe_speed() -> u64 {
+    100
+}
+
+fn default_fan_stall_time() -> u64 {
+    30
+}
+
+fn default_control_check_interval() -> u64 {
+    30
+}
+
+fn default_fan_speed_tolerance() -> u64 {
+    5
+}
+
+fn default_max_write_failures() -> u64 {
+    5
+}
+
+fn default_exit_speed() -> u64 {
+    100
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            backend: BackendKind::default(),
+            nvml_library_path: None,
+            sysfs_root: default_sysfs_root(),
+            replay_trace: None,
+            escalation: Escalation::default(),
+            helper_socket: None,
+            helper_allowed_users: Vec::new(),
+            display: None,
+            xauthority: None,
+            settings_gpu_target: None,
+            settings_fan_targets: Vec::new(),
             gpu_id: 0,
+            sensor: SensorSelection::default(),
+            temp_sources: Vec::new(),
+            temp_combine: TempCombine::default(),
             temp_thresholds: vec![48, 58, 68, 78, 86],
             fan_speeds: vec![46, 55, 62, 80, 100],
             fan_speed_floor: 46,
@@ -44,11 +579,34 @@ impl Default for Config {
             sampling_window_size: 10,
             hysteresis: 3,
             global_delay: 2,
+            command_timeout: default_command_timeout(),
             fan_dwell_time: 10,
+            mode: ControlMode::default(),
+            pid_target_temp: default_pid_target_temp(),
+            pid_kp: default_pid_kp(),
+            pid_ki: default_pid_ki(),
+            pid_kd: default_pid_kd(),
+            pid_derivative_filter: default_pid_derivative_filter(),
+            interpolation: None,
             smooth_mode: true,
             smooth_mode_incr_weight: 1.0,
             smooth_mode_decr_weight: 2.0,
             smooth_mode_max_fan_step: 10,
+            failsafe_after: default_failsafe_after(),
+            failsafe_recover_after: default_failsafe_recover_after(),
+            failsafe_mode: FailsafeMode::default(),
+            failsafe_speed: default_failsafe_speed(),
+            fan_stall_time: default_fan_stall_time(),
+            fan_stall_action: StallAction::default(),
+            control_check_interval: default_control_check_interval(),
+            fan_speed_tolerance: default_fan_speed_tolerance(),
+            max_write_failures: default_max_write_failures(),
+            exit_mode: ExitMode::default(),
+            exit_speed: default_exit_speed(),
+            gpus: Vec::new(),
+            pwm_outputs: Vec::new(),
+            fans: Vec::new(),
+            sim: None,
         }
     }
 }
@@ -65,6 +623,18 @@ impl fmt::Display for ConfigError {
                 f,
                 "Temperature and Fan Speed arrays must be the same length"
             ),
+            ConfigError::UnsortedCurve => write!(
+                f,
+                "Temperature thresholds must increase and fan speeds can't decrease along a curve"
+            ),
+            ConfigError::DuplicateGpu(id) => {
+                write!(f, "GPU {} has more than one [[gpu]] section", id)
+            }
+            ConfigError::InvalidPidSetting(name) => write!(
+                f,
+                "'{}' is out of range, PID gains can't be negative and the filter must be below 1",
+                name
+            ),
         }
     }
 }
@@ -118,6 +688,19 @@ pub fn get_config_path(custom_path: Option<String>) -> Result<PathBuf, ConfigErr
     resolve_path(&path_str)
 }
 
+/// A curve pairs each threshold with a speed, and the interpolation expects both sorted
+fn check_curve(temps: &[u64], speeds: &[u64]) -> Result<(), ConfigError> {
+    if temps.len() != speeds.len() {
+        return Err(ConfigError::InvalidArrayFormat);
+    }
+    let increasing = temps.windows(2).all(|pair| pair[0] < pair[1]);
+    let non_decreasing = speeds.windows(2).all(|pair| pair[0] <= pair[1]);
+    if !increasing || !non_decreasing {
+        return Err(ConfigError::UnsortedCurve);
+    }
+    Ok(())
+}
+
 impl Config {
     pub fn new(custom_path: Option<String>) -> Result<Config, ConfigError> {
         let file_path = get_config_path(custom_path)?;
@@ -137,12 +720,91 @@ impl Config {
             .map_err(ConfigError::Io)?;
 
         let config: Self = toml::from_str(&contents).map_err(ConfigError::Toml)?;
+        config.validate()?;
 
-        if config.fan_speeds.len() != config.temp_thresholds.len() {
-            return Err(ConfigError::InvalidArrayFormat);
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let gains = [
+            ("pid_kp", self.pid_kp),
+            ("pid_ki", self.pid_ki),
+            ("pid_kd", self.pid_kd),
+        ];
+        if let Some((name, _)) = gains.iter().find(|(_, gain)| gain.is_nan() || *gain < 0.0) {
+            return Err(ConfigError::InvalidPidSetting(name));
+        }
+        if !(0.0..1.0).contains(&self.pid_derivative_filter) {
+            return Err(ConfigError::InvalidPidSetting("pid_derivative_filter"));
         }
 
-        Ok(config)
+        for (index, gpu) in self.gpus.iter().enumerate() {
+            if self.gpus[..index]
+                .iter()
+                .any(|other| other.gpu_id == gpu.gpu_id)
+            {
+                return Err(ConfigError::DuplicateGpu(gpu.gpu_id));
+            }
+        }
+
+        for config in self
+            .control_targets()
+            .into_iter()
+            .map(|target| target.config)
+        {
+            check_curve(&config.temp_thresholds, &config.fan_speeds)?;
+
+            for fan in &config.fans {
+                let temps = fan
+                    .temp_thresholds
+                    .as_ref()
+                    .unwrap_or(&config.temp_thresholds);
+                let speeds = fan.fan_speeds.as_ref().unwrap_or(&config.fan_speeds);
+                check_curve(temps, speeds)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The config of each controlled GPU, just this one without `[[gpu]]` sections
+    pub fn gpu_configs(&self) -> Vec<Config> {
+        if self.gpus.is_empty() {
+            return vec![Config {
+                pwm_outputs: Vec::new(),
+                ..self.clone()
+            }];
+        }
+        self.gpus.iter().map(|gpu| gpu.apply(self)).collect()
+    }
+
+    /// Every GPU followed by every PWM output, each one gets a control loop
+    pub fn control_targets(&self) -> Vec<ControlTarget> {
+        let gpus = self.gpu_configs();
+        let outputs = self.pwm_outputs.iter().map(|output| {
+            let gpu_id = output.gpu_id.unwrap_or(self.gpu_id);
+            let gpu = gpus
+                .iter()
+                .find(|gpu| gpu.gpu_id == gpu_id)
+                .cloned()
+                .unwrap_or_else(|| Config {
+                    gpu_id,
+                    gpus: Vec::new(),
+                    ..self.clone()
+                });
+            ControlTarget {
+                config: output.apply(&gpu),
+                pwm_output: Some(output.clone()),
+            }
+        });
+
+        gpus.iter()
+            .map(|gpu| ControlTarget {
+                config: gpu.clone(),
+                pwm_output: None,
+            })
+            .chain(outputs)
+            .collect::<Vec<_>>()
     }
 
     pub fn write_to_file(&self, custom_path: Option<String>) -> Result<(), ConfigError> {
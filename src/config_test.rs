@@ -139,3 +139,311 @@ fn test_load_config_from_env() {
         config::load_config_from_env(Some(config_path.to_str().unwrap().to_string())).unwrap();
     assert_eq!(config.gpu_id, config::Config::default().gpu_id);
 }
+
+#[test]
+fn test_mismatched_fan_override_arrays() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("fan_override_config.toml");
+
+    let mut config = config::Config::default();
+    config.fans = vec![config::FanConfig {
+        index: 1,
+        temp_thresholds: Some(vec![40, 60]),
+        ..config::FanConfig::default()
+    }];
+    config
+        .write_to_file(Some(config_path.to_str().unwrap().to_string()))
+        .unwrap();
+
+    let result = config::Config::new(Some(config_path.to_str().unwrap().to_string()));
+    assert!(matches!(
+        result,
+        Err(config::ConfigError::InvalidArrayFormat)
+    ));
+
+    // a full override round-trips through the file
+    config.fans[0].fan_speeds = Some(vec![30, 70]);
+    config
+        .write_to_file(Some(config_path.to_str().unwrap().to_string()))
+        .unwrap();
+    let read_config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    assert_eq!(read_config.fans, config.fans);
+}
+
+#[test]
+fn test_sensor_selection_parsing() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("sensor_config.toml");
+
+    let mut config = config::Config::default();
+    config.sensor =
+        config::SensorSelection::Max(vec![config::Sensor::Core, config::Sensor::Memory]);
+    config
+        .write_to_file(Some(config_path.to_str().unwrap().to_string()))
+        .unwrap();
+    let contents = fs::read_to_string(&config_path).unwrap();
+    assert!(contents.contains(r#"sensor = ["core", "memory"]"#));
+    let read_config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    assert_eq!(read_config.sensor, config.sensor);
+
+    let single = contents.replace(r#"sensor = ["core", "memory"]"#, r#"sensor = "hotspot""#);
+    fs::write(&config_path, single).unwrap();
+    let read_config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    assert_eq!(
+        read_config.sensor,
+        config::SensorSelection::Single(config::Sensor::Hotspot)
+    );
+}
+
+#[test]
+fn test_gpu_sections() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("gpu_config.toml");
+
+    let defaults = toml::to_string(&config::Config::default()).unwrap();
+    let contents = format!(
+        r#"{}
+[[gpu]]
+gpu_id = 0
+
+[[gpu]]
+gpu_id = 2
+settings_fan_targets = [2, 3]
+temp_thresholds = [50, 70]
+fan_speeds = [40, 90]
+smooth_mode = false
+"#,
+        defaults
+    );
+    fs::write(&config_path, &contents).unwrap();
+
+    let config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    let gpus = config.gpu_configs();
+    assert_eq!(gpus.len(), 2);
+    assert_eq!(gpus[0].gpu_id, 0);
+    assert_eq!(gpus[0].temp_thresholds, config.temp_thresholds);
+    assert!(gpus[0].smooth_mode);
+    assert_eq!(gpus[1].gpu_id, 2);
+    assert_eq!(gpus[1].settings_fan_targets, vec![2, 3]);
+    assert_eq!(gpus[1].temp_thresholds, vec![50, 70]);
+    assert_eq!(gpus[1].fan_speeds, vec![40, 90]);
+    assert!(!gpus[1].smooth_mode);
+    assert_eq!(gpus[1].global_delay, config.global_delay);
+    assert!(gpus.iter().all(|gpu| gpu.gpus.is_empty()));
+
+    // without sections the top-level settings describe the only GPU
+    assert_eq!(config::Config::default().gpu_configs().len(), 1);
+
+    fs::write(&config_path, contents.replace("gpu_id = 2", "gpu_id = 0")).unwrap();
+    let result = config::Config::new(Some(config_path.to_str().unwrap().to_string()));
+    assert!(matches!(result, Err(config::ConfigError::DuplicateGpu(0))));
+
+    fs::write(&config_path, contents.replace("fan_speeds = [40, 90]", "")).unwrap();
+    let result = config::Config::new(Some(config_path.to_str().unwrap().to_string()));
+    assert!(matches!(
+        result,
+        Err(config::ConfigError::InvalidArrayFormat)
+    ));
+}
+
+#[test]
+fn test_pwm_output_sections() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("pwm_output_config.toml");
+
+    let defaults = toml::to_string(&config::Config::default()).unwrap();
+    let contents = format!(
+        r#"{}
+[[gpu]]
+gpu_id = 1
+temp_thresholds = [50, 70]
+fan_speeds = [40, 90]
+
+[[pwm_output]]
+hwmon = "nct6798"
+channel = 2
+gpu_id = 1
+sensor = "memory"
+fan_speed_floor = 20
+
+[[pwm_output]]
+hwmon = "nct6798"
+channel = 3
+temp_thresholds = [40, 60, 80]
+fan_speeds = [30, 60, 100]
+"#,
+        defaults
+    );
+    fs::write(&config_path, &contents).unwrap();
+
+    let config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    let targets = config.control_targets();
+    assert_eq!(targets.len(), 3);
+    assert_eq!(targets[0].label(), "GPU 1");
+    assert!(targets[0].pwm_output.is_none());
+    assert!(targets[0].config.pwm_outputs.is_empty());
+
+    // an output follows its GPU's section, overriding only what it sets
+    assert_eq!(targets[1].label(), "nct6798/pwm2");
+    assert_eq!(targets[1].config.gpu_id, 1);
+    assert_eq!(targets[1].config.temp_thresholds, vec![50, 70]);
+    assert_eq!(
+        targets[1].config.sensor,
+        config::SensorSelection::Single(config::Sensor::Memory)
+    );
+    assert_eq!(targets[1].config.fan_speed_floor, 20);
+
+    // a GPU without a section uses the top-level settings
+    assert_eq!(targets[2].label(), "nct6798/pwm3");
+    assert_eq!(targets[2].config.gpu_id, config.gpu_id);
+    assert_eq!(targets[2].config.fan_speeds, vec![30, 60, 100]);
+    assert!(targets[2].config.gpus.is_empty());
+    assert!(targets[2].config.pwm_outputs.is_empty());
+
+    fs::write(
+        &config_path,
+        contents.replace("fan_speeds = [30, 60, 100]", "fan_speeds = [30, 100]"),
+    )
+    .unwrap();
+    let result = config::Config::new(Some(config_path.to_str().unwrap().to_string()));
+    assert!(matches!(
+        result,
+        Err(config::ConfigError::InvalidArrayFormat)
+    ));
+}
+
+#[test]
+fn test_unsorted_curves() {
+    let valid = config::Config::default();
+    assert!(valid.validate().is_ok());
+
+    // a falling speed would make the linear interpolation underflow
+    let config = config::Config {
+        fan_speeds: vec![46, 55, 40, 80, 100],
+        ..valid.clone()
+    };
+    assert!(matches!(
+        config.validate(),
+        Err(config::ConfigError::UnsortedCurve)
+    ));
+
+    let config = config::Config {
+        temp_thresholds: vec![40, 60, 50, 78, 84],
+        ..valid.clone()
+    };
+    assert!(matches!(
+        config.validate(),
+        Err(config::ConfigError::UnsortedCurve)
+    ));
+
+    // flat stretches of speed are fine, repeated thresholds are not
+    let mut config = config::Config {
+        fan_speeds: vec![46, 55, 55, 80, 100],
+        ..valid.clone()
+    };
+    assert!(config.validate().is_ok());
+    config.fans = vec![config::FanConfig {
+        index: 1,
+        temp_thresholds: Some(vec![40, 40]),
+        fan_speeds: Some(vec![30, 70]),
+        ..config::FanConfig::default()
+    }];
+    assert!(matches!(
+        config.validate(),
+        Err(config::ConfigError::UnsortedCurve)
+    ));
+
+    let config = config::Config {
+        pwm_outputs: vec![config::PwmOutputConfig {
+            hwmon: "nct6798".to_string(),
+            channel: 1,
+            fan_speeds: Some(vec![100, 80, 60, 40, 20]),
+            ..config::PwmOutputConfig::default()
+        }],
+        ..valid
+    };
+    assert!(matches!(
+        config.validate(),
+        Err(config::ConfigError::UnsortedCurve)
+    ));
+}
+
+#[test]
+fn test_temp_sources_parsing() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("temp_sources_config.toml");
+
+    let mut config = config::Config::default();
+    config.temp_sources = vec![
+        config::TempSource {
+            gpu_id: 0,
+            sensor: config::Sensor::Core,
+            weight: 1.0,
+        },
+        config::TempSource {
+            gpu_id: 1,
+            sensor: config::Sensor::Memory,
+            weight: 2.0,
+        },
+    ];
+    config.temp_combine = config::TempCombine::Weighted;
+    config
+        .write_to_file(Some(config_path.to_str().unwrap().to_string()))
+        .unwrap();
+    let read_config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    assert_eq!(read_config.temp_sources, config.temp_sources);
+    assert_eq!(read_config.temp_combine, config::TempCombine::Weighted);
+
+    // the README form, inline tables with the sensor and weight left out
+    let inline = format!(
+        "temp_sources = [{{ gpu_id = 0 }}, {{ gpu_id = 2, sensor = \"hotspot\" }}]\n{}",
+        toml::to_string(&config::Config::default()).unwrap()
+    );
+    fs::write(&config_path, inline).unwrap();
+    let read_config = config::Config::new(Some(config_path.to_str().unwrap().to_string())).unwrap();
+    assert_eq!(read_config.temp_sources.len(), 2);
+    assert_eq!(read_config.temp_sources[0].weight, 1.0);
+    assert_eq!(read_config.temp_sources[1].sensor, config::Sensor::Hotspot);
+}
+
+#[test]
+fn test_pid_settings() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("pid_config.toml");
+    let path = Some(config_path.to_str().unwrap().to_string());
+
+    let config = config::Config {
+        mode: config::ControlMode::Pid,
+        pid_target_temp: 68,
+        ..config::Config::default()
+    };
+    config.write_to_file(path.clone()).unwrap();
+    let loaded = config::Config::new(path.clone()).unwrap();
+    assert_eq!(loaded.mode, config::ControlMode::Pid);
+    assert_eq!(loaded.pid_target_temp, 68);
+    assert_eq!(loaded.pid_kp, config.pid_kp);
+
+    let invalid = vec![
+        (
+            "pid_kd",
+            config::Config {
+                pid_kd: -1.0,
+                ..config.clone()
+            },
+        ),
+        (
+            "pid_derivative_filter",
+            config::Config {
+                pid_derivative_filter: 1.0,
+                ..config.clone()
+            },
+        ),
+    ];
+    for (name, config) in invalid {
+        assert!(
+            matches!(config.validate(), Err(config::ConfigError::InvalidPidSetting(n)) if n == name),
+            "{}",
+            name
+        );
+    }
+}
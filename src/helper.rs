@@ -0,0 +1,453 @@
+use nix::unistd::{getuid, Uid, User};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{GpuBackend, GpuSnapshot};
+use crate::commands::{self, SettingsContext};
+use crate::config::{Config, Escalation};
+
+pub const DEFAULT_SOCKET_PATH: &str = "/run/veridian-controller.sock";
+
+// generous for "set 255 63 100", anything longer is not a valid request
+const MAX_REQUEST_LEN: u64 = 64;
+const MAX_FAN_INDEX: u64 = 63;
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+// clients are served one at a time, so each connection gets this long in total; it stays
+// well below CLIENT_TIMEOUT so a queued client isn't timed out behind a slow one
+const CONNECTION_DEADLINE: Duration = Duration::from_secs(2);
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub enum HelperError {
+    Io(io::Error),
+    InvalidRequest(String),
+    UnknownUser(String),
+    Unauthorized(u32),
+    Rejected(String),
+    NotASocket(PathBuf),
+    AlreadyRunning(PathBuf),
+    NotRoot,
+}
+
+impl fmt::Display for HelperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HelperError::Io(err) => write!(f, "Helper socket error: {}", err),
+            HelperError::InvalidRequest(line) => write!(f, "Invalid helper request '{}'", line),
+            HelperError::UnknownUser(name) => write!(f, "Unknown user '{}' in allow-list", name),
+            HelperError::Unauthorized(uid) => {
+                write!(f, "uid {} is not allowed to use the helper", uid)
+            }
+            HelperError::Rejected(msg) => write!(f, "Helper rejected the request: {}", msg),
+            HelperError::NotASocket(path) => write!(
+                f,
+                "'{}' exists and is not a socket, refusing to replace it",
+                path.display()
+            ),
+            HelperError::AlreadyRunning(path) => write!(
+                f,
+                "Another helper is already listening on '{}'",
+                path.display()
+            ),
+            HelperError::NotRoot => write!(f, "The helper must run as root"),
+        }
+    }
+}
+impl std::error::Error for HelperError {}
+
+impl From<io::Error> for HelperError {
+    fn from(err: io::Error) -> Self {
+        HelperError::Io(err)
+    }
+}
+
+/// The only operations the helper performs, one per line on the socket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    Acquire { gpu: u8 },
+    Release { gpu: u8 },
+    Set { gpu: u8, fan: u64, speed: u64 },
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Result<Self, HelperError> {
+        let invalid = || HelperError::InvalidRequest(line.to_string());
+        let parts: Vec<&str> = line.trim_end_matches('\n').split(' ').collect();
+        let gpu = |field: &str| field.parse::<u8>().map_err(|_| invalid());
+
+        match parts.as_slice() {
+            ["acquire", id] => Ok(Request::Acquire { gpu: gpu(id)? }),
+            ["release", id] => Ok(Request::Release { gpu: gpu(id)? }),
+            ["set", id, fan, speed] => {
+                let fan = fan
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|fan| *fan <= MAX_FAN_INDEX)
+                    .ok_or_else(invalid)?;
+                let speed = speed
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|speed| *speed <= 100)
+                    .ok_or_else(invalid)?;
+                Ok(Request::Set {
+                    gpu: gpu(id)?,
+                    fan,
+                    speed,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn to_line(self) -> String {
+        match self {
+            Request::Acquire { gpu } => format!("acquire {}\n", gpu),
+            Request::Release { gpu } => format!("release {}\n", gpu),
+            Request::Set { gpu, fan, speed } => format!("set {} {} {}\n", gpu, fan, speed),
+        }
+    }
+}
+
+/// Returns the uid of the process on the other end of `stream`
+pub fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// Users allowed to send requests, root gets no implicit pass since it doesn't need the helper
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AllowList {
+    pub uids: Vec<u32>,
+}
+
+impl AllowList {
+    pub fn from_users(names: &[String]) -> Result<Self, HelperError> {
+        let uids = names
+            .iter()
+            .map(|name| match User::from_name(name) {
+                Ok(Some(user)) => Ok(user.uid.as_raw()),
+                _ => Err(HelperError::UnknownUser(name.clone())),
+            })
+            .collect::<Result<Vec<u32>, HelperError>>()?;
+        Ok(AllowList { uids })
+    }
+
+    pub fn permits(&self, uid: u32) -> bool {
+        self.uids.contains(&uid)
+    }
+}
+
+/// Reads one request line, `None` once the client hangs up. Every read only gets the time
+/// left until `deadline`, so a client trickling bytes can't hold the helper
+fn read_request(
+    reader: &mut BufReader<UnixStream>,
+    deadline: Instant,
+) -> Result<Option<String>, HelperError> {
+    let mut line = Vec::new();
+    loop {
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "client took too long");
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(timed_out().into());
+        }
+        reader.get_ref().set_read_timeout(Some(remaining))?;
+
+        let buffer = match reader.fill_buf() {
+            Ok(buffer) => buffer,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(timed_out().into()),
+            Err(e) => return Err(e.into()),
+        };
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        let (chunk, complete) = match buffer.iter().position(|byte| *byte == b'\n') {
+            Some(end) => (&buffer[..=end], true),
+            None => (buffer, false),
+        };
+        line.extend_from_slice(chunk);
+        let consumed = chunk.len();
+        reader.consume(consumed);
+
+        if line.len() as u64 > MAX_REQUEST_LEN {
+            return Err(HelperError::InvalidRequest(
+                String::from_utf8_lossy(&line).into_owned(),
+            ));
+        }
+        if complete {
+            return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+        }
+    }
+}
+
+fn handle_client(
+    stream: UnixStream,
+    allow: &AllowList,
+    handler: &mut dyn FnMut(Request) -> Result<(), Box<dyn Error>>,
+) -> Result<(), HelperError> {
+    let uid = peer_uid(&stream)?;
+    let mut writer = stream.try_clone()?;
+    if !allow.permits(uid) {
+        writeln!(writer, "err uid {} is not allowed", uid)?;
+        return Err(HelperError::Unauthorized(uid));
+    }
+
+    writer.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let deadline = Instant::now() + CONNECTION_DEADLINE;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let line = match read_request(&mut reader, deadline) {
+            Ok(Some(line)) => line,
+            Ok(None) => return Ok(()),
+            Err(e @ HelperError::InvalidRequest(_)) => {
+                // oversized, drop the connection rather than resync
+                writeln!(writer, "err request too long")?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let response = match Request::parse(&line) {
+            Ok(request) => match handler(request) {
+                Ok(()) => {
+                    println!("Helper: uid {} {}", uid, line.trim_end());
+                    "ok".to_string()
+                }
+                Err(e) => format!("err {}", e),
+            },
+            Err(e) => format!("err {}", e),
+        };
+        writeln!(writer, "{}", response.replace('\n', " "))?;
+    }
+}
+
+/// Accepts clients on `socket_path` until `stop` is set, passing valid requests to `handler`
+pub fn serve(
+    socket_path: &Path,
+    allow: &AllowList,
+    stop: &AtomicBool,
+    handler: &mut dyn FnMut(Request) -> Result<(), Box<dyn Error>>,
+) -> Result<(), HelperError> {
+    // a previous helper may have left its socket behind, anything else at the path is kept
+    match fs::symlink_metadata(socket_path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            // only a socket nobody listens on is stale, a running helper keeps its own
+            match UnixStream::connect(socket_path) {
+                Ok(_) => return Err(HelperError::AlreadyRunning(socket_path.to_path_buf())),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    fs::remove_file(socket_path)?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(_) => return Err(HelperError::NotASocket(socket_path.to_path_buf())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    // anyone may connect, requests are authorised by peer credentials
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o666))?;
+    listener.set_nonblocking(true)?;
+
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                if let Err(e) = handle_client(stream, allow, handler) {
+                    eprintln!("Helper: {}", e);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+            Err(e) => eprintln!("Helper: failed to accept a client: {}", e),
+        }
+    }
+
+    let _ = fs::remove_file(socket_path);
+    Ok(())
+}
+
+/// Runs the root helper with the socket and allow-list from `config`
+pub fn run(config: &Config, stop: &AtomicBool) -> Result<(), Box<dyn Error>> {
+    if !Uid::is_root(getuid()) {
+        return Err(HelperError::NotRoot.into());
+    }
+    let socket_path = config
+        .helper_socket
+        .as_deref()
+        .unwrap_or(DEFAULT_SOCKET_PATH);
+    let allow = AllowList::from_users(&config.helper_allowed_users)?;
+    if allow.uids.is_empty() {
+        eprintln!("Helper: 'helper_allowed_users' is empty, every request will be refused");
+    }
+    // the controller already mapped its GPU and fans to nvidia-settings targets
+    let settings = SettingsContext {
+        escalation: Escalation::None,
+        fan_targets: Vec::new(),
+        ..SettingsContext::from_config(config)
+    };
+    if let Err(e) = commands::check_display(&settings) {
+        eprintln!("Helper: {}", e);
+    }
+
+    println!("Helper listening on {}", socket_path);
+    let mut handler = |request: Request| -> Result<(), Box<dyn Error>> {
+        match request {
+            Request::Acquire { gpu } => commands::set_fan_control(
+                &SettingsContext {
+                    gpu_target: gpu,
+                    ..settings.clone()
+                },
+                1,
+            )?,
+            Request::Release { gpu } => commands::set_fan_control(
+                &SettingsContext {
+                    gpu_target: gpu,
+                    ..settings.clone()
+                },
+                0,
+            )?,
+            Request::Set { gpu, fan, speed } => commands::set_fan_speed_of(
+                &SettingsContext {
+                    gpu_target: gpu,
+                    ..settings.clone()
+                },
+                fan,
+                speed,
+            )?,
+        }
+        Ok(())
+    };
+    serve(Path::new(socket_path), &allow, stop, &mut handler)?;
+    Ok(())
+}
+
+/// Sends requests to a running helper, one connection per request
+pub struct HelperClient {
+    pub socket_path: PathBuf,
+}
+
+impl HelperClient {
+    pub fn send(&self, request: Request) -> Result<(), HelperError> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.write_all(request.to_line().as_bytes())?;
+
+        let mut response = String::new();
+        BufReader::new(&stream)
+            .take(1024)
+            .read_line(&mut response)?;
+        match response.trim_end() {
+            "ok" => Ok(()),
+            other => Err(HelperError::Rejected(
+                other.strip_prefix("err ").unwrap_or(other).to_string(),
+            )),
+        }
+    }
+}
+
+/// Reads through `inner` but sends every fan write to the root helper
+pub struct HelperBackend {
+    inner: Box<dyn GpuBackend>,
+    client: HelperClient,
+    /// Only the nvidia-settings targets are used, the helper has its own display
+    settings: SettingsContext,
+}
+
+impl HelperBackend {
+    pub fn new(
+        inner: Box<dyn GpuBackend>,
+        socket_path: PathBuf,
+        settings: SettingsContext,
+    ) -> Self {
+        HelperBackend {
+            inner,
+            client: HelperClient { socket_path },
+            settings,
+        }
+    }
+}
+
+impl GpuBackend for HelperBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        self.inner.get_snapshot()
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        self.inner.read_fan_speed()
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.inner.get_fan_count()
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_rpms()
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_speeds()
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        self.inner.get_fan_control()
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.client.send(Request::Acquire {
+            gpu: self.settings.gpu_target,
+        })?)
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.client.send(Request::Release {
+            gpu: self.settings.gpu_target,
+        })?)
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        // the protocol only addresses single fans
+        for fan in 0..self.inner.get_fan_count() {
+            self.set_fan_speed_of(fan, speed)?;
+        }
+        Ok(())
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        Ok(self.client.send(Request::Set {
+            gpu: self.settings.gpu_target,
+            fan: self.settings.fan_target(fan),
+            speed,
+        })?)
+    }
+}
@@ -0,0 +1,295 @@
+use nix::unistd::getuid;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+use crate::backend::GpuBackend;
+use crate::commands::SettingsContext;
+use crate::config::{Config, SimConfig};
+use crate::helper::{self, AllowList, HelperBackend, HelperClient, HelperError, Request};
+use crate::sim::SimBackend;
+
+struct TestHelper {
+    stop: Arc<AtomicBool>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestHelper {
+    fn start(socket_path: &Path, allow: AllowList) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let requests = Arc::clone(&requests);
+            let socket_path = socket_path.to_path_buf();
+            thread::spawn(move || {
+                let mut handler = |request: Request| -> Result<(), Box<dyn Error>> {
+                    if request
+                        == (Request::Set {
+                            gpu: 0,
+                            fan: 3,
+                            speed: 50,
+                        })
+                    {
+                        return Err("no such fan".into());
+                    }
+                    requests.lock().unwrap().push(request);
+                    Ok(())
+                };
+                helper::serve(&socket_path, &allow, &stop, &mut handler).unwrap();
+            })
+        };
+
+        let started = Instant::now();
+        while !socket_path.exists() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        TestHelper {
+            stop,
+            requests,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for TestHelper {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+fn socket_in(dir: &TempDir) -> PathBuf {
+    dir.path().join("helper.sock")
+}
+
+fn current_user() -> AllowList {
+    AllowList {
+        uids: vec![getuid().as_raw()],
+    }
+}
+
+#[test]
+fn test_parse_requests() {
+    let cases = vec![
+        ("acquire 0\n", Request::Acquire { gpu: 0 }),
+        ("release 1", Request::Release { gpu: 1 }),
+        (
+            "set 0 1 100\n",
+            Request::Set {
+                gpu: 0,
+                fan: 1,
+                speed: 100,
+            },
+        ),
+    ];
+    for (line, expected) in cases {
+        let request = Request::parse(line).unwrap();
+        assert_eq!(request, expected, "line {:?}", line);
+        assert_eq!(Request::parse(&request.to_line()).unwrap(), expected);
+    }
+
+    let invalid = vec![
+        "",
+        "acquire",
+        "ACQUIRE 0",
+        "acquire  0",
+        "acquire 256",
+        "release -1",
+        "set 0 0 101",
+        "set 0 64 50",
+        "set 0 0 50 extra",
+        "set 0 0 5O",
+        "exec rm -rf /",
+    ];
+    for line in invalid {
+        assert!(
+            matches!(Request::parse(line), Err(HelperError::InvalidRequest(_))),
+            "line {:?} should be rejected",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_helper_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket_path = socket_in(&temp_dir);
+    let helper = TestHelper::start(&socket_path, current_user());
+    let client = HelperClient {
+        socket_path: socket_path.clone(),
+    };
+
+    client.send(Request::Acquire { gpu: 0 }).unwrap();
+    client
+        .send(Request::Set {
+            gpu: 0,
+            fan: 1,
+            speed: 65,
+        })
+        .unwrap();
+    match client.send(Request::Set {
+        gpu: 0,
+        fan: 3,
+        speed: 50,
+    }) {
+        Err(HelperError::Rejected(msg)) => assert_eq!(msg, "no such fan"),
+        other => panic!("Expected a rejection, got {:?}", other),
+    }
+
+    // raw clients can't get anything past the parser
+    let mut stream = UnixStream::connect(&socket_path).unwrap();
+    stream.write_all(b"set 0 0 150\nrelease 0\n").unwrap();
+    let mut reader = BufReader::new(&stream);
+    let mut response = String::new();
+    reader.read_line(&mut response).unwrap();
+    assert!(response.starts_with("err Invalid helper request"));
+    response.clear();
+    reader.read_line(&mut response).unwrap();
+    assert_eq!(response, "ok\n");
+    drop(reader);
+    drop(stream);
+
+    let mut backend = HelperBackend::new(
+        Box::new(SimBackend::new(SimConfig::default())),
+        socket_path.clone(),
+        SettingsContext {
+            gpu_target: 1,
+            fan_targets: vec![2],
+            ..SettingsContext::from_config(&Config::default())
+        },
+    );
+    backend.set_fan_speed(80).unwrap();
+
+    assert_eq!(
+        *helper.requests.lock().unwrap(),
+        vec![
+            Request::Acquire { gpu: 0 },
+            Request::Set {
+                gpu: 0,
+                fan: 1,
+                speed: 65
+            },
+            Request::Release { gpu: 0 },
+            Request::Set {
+                gpu: 1,
+                fan: 2,
+                speed: 80
+            },
+        ]
+    );
+
+    drop(helper);
+    assert!(!socket_path.exists(), "The socket is removed on shutdown");
+}
+
+#[test]
+fn test_helper_rejects_unlisted_users() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket_path = socket_in(&temp_dir);
+    let helper = TestHelper::start(&socket_path, AllowList::default());
+    let client = HelperClient {
+        socket_path: socket_path.clone(),
+    };
+
+    match client.send(Request::Release { gpu: 0 }) {
+        Err(HelperError::Rejected(msg)) => assert!(msg.contains("is not allowed")),
+        other => panic!("Expected a rejection, got {:?}", other),
+    }
+    assert!(helper.requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_allow_list_from_users() {
+    let allow = AllowList::from_users(&["root".to_string()]).unwrap();
+    assert_eq!(allow.uids, vec![0]);
+    assert!(allow.permits(0));
+    assert!(!allow.permits(1000));
+
+    let result = AllowList::from_users(&["no-such-user-veridian".to_string()]);
+    assert!(matches!(result, Err(HelperError::UnknownUser(_))));
+}
+
+#[test]
+fn test_helper_keeps_non_socket_path() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket_path = socket_in(&temp_dir);
+    std::fs::write(&socket_path, "not a socket").unwrap();
+
+    let mut handler = |_: Request| -> Result<(), Box<dyn Error>> { Ok(()) };
+    let result = helper::serve(
+        &socket_path,
+        &current_user(),
+        &AtomicBool::new(false),
+        &mut handler,
+    );
+    assert!(matches!(result, Err(HelperError::NotASocket(_))));
+    assert_eq!(
+        std::fs::read_to_string(&socket_path).unwrap(),
+        "not a socket"
+    );
+}
+
+#[test]
+fn test_helper_keeps_live_socket() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket_path = socket_in(&temp_dir);
+    let helper = TestHelper::start(&socket_path, current_user());
+
+    let mut handler = |_: Request| -> Result<(), Box<dyn Error>> { Ok(()) };
+    let result = helper::serve(
+        &socket_path,
+        &current_user(),
+        &AtomicBool::new(false),
+        &mut handler,
+    );
+    assert!(matches!(result, Err(HelperError::AlreadyRunning(_))));
+    HelperClient {
+        socket_path: socket_path.clone(),
+    }
+    .send(Request::Acquire { gpu: 0 })
+    .unwrap();
+    assert_eq!(
+        *helper.requests.lock().unwrap(),
+        vec![Request::Acquire { gpu: 0 }]
+    );
+}
+
+#[test]
+fn test_helper_replaces_stale_socket() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket_path = socket_in(&temp_dir);
+    // a helper that died without removing its socket
+    drop(UnixListener::bind(&socket_path).unwrap());
+
+    let helper = TestHelper::start(&socket_path, current_user());
+    // the stale socket already exists, wait for the helper's own one
+    let started = Instant::now();
+    while UnixStream::connect(&socket_path).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "Helper never listened"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    HelperClient {
+        socket_path: socket_path.clone(),
+    }
+    .send(Request::Acquire { gpu: 1 })
+    .unwrap();
+    assert_eq!(
+        *helper.requests.lock().unwrap(),
+        vec![Request::Acquire { gpu: 1 }]
+    );
+}
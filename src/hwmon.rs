@@ -0,0 +1,347 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::{GpuBackend, GpuSnapshot};
+
+const PWM_MAX: u64 = 255;
+const PWM_ENABLE_MANUAL: u64 = 1;
+const PWM_ENABLE_AUTO: u64 = 2;
+
+#[derive(Debug)]
+pub enum HwmonError {
+    Io(PathBuf, std::io::Error),
+    MissingHwmon(PathBuf),
+    MissingTempInput(PathBuf),
+    UnknownDevice(String),
+    MissingPwm(PathBuf),
+    InvalidValue(PathBuf, String),
+}
+
+impl fmt::Display for HwmonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HwmonError::Io(path, err) => write!(f, "IO error on '{}': {}", path.display(), err),
+            HwmonError::MissingHwmon(path) => {
+                write!(f, "No hwmon directory found under '{}'", path.display())
+            }
+            HwmonError::MissingTempInput(path) => {
+                write!(f, "No temp*_input found in '{}'", path.display())
+            }
+            HwmonError::UnknownDevice(name) => write!(f, "No hwmon device named '{}'", name),
+            HwmonError::MissingPwm(path) => write!(f, "No PWM channel at '{}'", path.display()),
+            HwmonError::InvalidValue(path, value) => {
+                write!(f, "Invalid value '{}' in '{}'", value, path.display())
+            }
+        }
+    }
+}
+impl std::error::Error for HwmonError {}
+
+pub fn percent_to_pwm(speed: u64) -> u64 {
+    (speed.min(100) * PWM_MAX + 50) / 100
+}
+
+pub fn pwm_to_percent(pwm: u64) -> u64 {
+    (pwm.min(PWM_MAX) * 100 + PWM_MAX / 2) / PWM_MAX
+}
+
+pub fn read_value(path: &Path) -> Result<u64, HwmonError> {
+    let contents = fs::read_to_string(path).map_err(|e| HwmonError::Io(path.to_path_buf(), e))?;
+    let trimmed = contents.trim();
+    trimmed
+        .parse::<u64>()
+        .map_err(|_| HwmonError::InvalidValue(path.to_path_buf(), trimmed.to_string()))
+}
+
+pub fn write_value(path: &Path, value: u64) -> Result<(), HwmonError> {
+    fs::write(path, value.to_string()).map_err(|e| HwmonError::Io(path.to_path_buf(), e))
+}
+
+/// Finds the first `hwmonN` directory that belongs to DRM card `card`
+pub fn find_hwmon_dir(sysfs_root: &Path, card: u8) -> Result<PathBuf, HwmonError> {
+    let hwmon_root = sysfs_root
+        .join("class/drm")
+        .join(format!("card{}", card))
+        .join("device/hwmon");
+
+    let entries =
+        fs::read_dir(&hwmon_root).map_err(|_| HwmonError::MissingHwmon(hwmon_root.clone()))?;
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("hwmon"))
+        })
+        .collect();
+    dirs.sort();
+
+    dirs.into_iter()
+        .next()
+        .ok_or(HwmonError::MissingHwmon(hwmon_root))
+}
+
+/// Finds the `hwmonN` directory whose `name` matches, e.g. "nct6798" for a motherboard chip
+pub fn find_hwmon_by_name(sysfs_root: &Path, name: &str) -> Result<PathBuf, HwmonError> {
+    let hwmon_root = sysfs_root.join("class/hwmon");
+    let entries =
+        fs::read_dir(&hwmon_root).map_err(|_| HwmonError::MissingHwmon(hwmon_root.clone()))?;
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            fs::read_to_string(path.join("name")).is_ok_and(|contents| contents.trim() == name)
+        })
+        .collect();
+    dirs.sort();
+
+    dirs.into_iter()
+        .next()
+        .ok_or_else(|| HwmonError::UnknownDevice(name.to_string()))
+}
+
+fn list_temp_inputs(hwmon_dir: &Path) -> Result<Vec<(u64, PathBuf)>, HwmonError> {
+    let entries =
+        fs::read_dir(hwmon_dir).map_err(|e| HwmonError::Io(hwmon_dir.to_path_buf(), e))?;
+    let mut inputs: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let index = name
+                .strip_prefix("temp")?
+                .strip_suffix("_input")?
+                .parse::<u64>()
+                .ok()?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// Returns the lowest numbered `temp*_input` file, which is the edge sensor on amdgpu
+pub fn find_temp_input(hwmon_dir: &Path) -> Result<PathBuf, HwmonError> {
+    list_temp_inputs(hwmon_dir)?
+        .into_iter()
+        .next()
+        .map(|(_, path)| path)
+        .ok_or(HwmonError::MissingTempInput(hwmon_dir.to_path_buf()))
+}
+
+/// Returns the `tempN_input` whose `tempN_label` matches, e.g. "junction" or "mem" on amdgpu
+pub fn find_labeled_temp_input(hwmon_dir: &Path, label: &str) -> Option<PathBuf> {
+    list_temp_inputs(hwmon_dir)
+        .ok()?
+        .into_iter()
+        .find(|(index, _)| {
+            fs::read_to_string(hwmon_dir.join(format!("temp{}_label", index)))
+                .is_ok_and(|contents| contents.trim() == label)
+        })
+        .map(|(_, path)| path)
+}
+
+/// Reads a GPU through the kernel hwmon interface and drives `pwm1` directly
+pub struct HwmonBackend {
+    pub hwmon_dir: PathBuf,
+    temp_input: PathBuf,
+    hotspot_input: Option<PathBuf>,
+    memory_input: Option<PathBuf>,
+}
+
+impl HwmonBackend {
+    pub fn new(sysfs_root: &Path, card: u8) -> Result<Self, HwmonError> {
+        let hwmon_dir = find_hwmon_dir(sysfs_root, card)?;
+        let temp_input = match find_labeled_temp_input(&hwmon_dir, "edge") {
+            Some(path) => path,
+            None => find_temp_input(&hwmon_dir)?,
+        };
+        Ok(HwmonBackend {
+            hotspot_input: find_labeled_temp_input(&hwmon_dir, "junction"),
+            memory_input: find_labeled_temp_input(&hwmon_dir, "mem"),
+            hwmon_dir,
+            temp_input,
+        })
+    }
+
+    fn pwm_path(&self) -> PathBuf {
+        self.hwmon_dir.join("pwm1")
+    }
+
+    fn pwm_enable_path(&self) -> PathBuf {
+        self.hwmon_dir.join("pwm1_enable")
+    }
+
+    fn read_temp(path: &Path) -> Result<u64, HwmonError> {
+        // hwmon reports millidegrees celsius
+        read_value(path).map(|millidegrees| (millidegrees / 1000).clamp(0, 200))
+    }
+
+    fn read_power_draw(&self) -> Option<f64> {
+        // hwmon reports microwatts, amdgpu exposes either an average or an instant value
+        ["power1_average", "power1_input"]
+            .iter()
+            .find_map(|name| read_value(&self.hwmon_dir.join(name)).ok())
+            .map(|microwatts| microwatts as f64 / 1_000_000.0)
+    }
+
+    fn read_utilization(&self) -> Option<u64> {
+        // hwmonN lives under device/hwmon, while gpu_busy_percent sits in device/
+        let device_dir = self.hwmon_dir.parent()?.parent()?;
+        read_value(&device_dir.join("gpu_busy_percent")).ok()
+    }
+
+    fn read_clock(&self, name: &str) -> Option<u64> {
+        read_value(&self.hwmon_dir.join(name))
+            .ok()
+            .map(|hz| hz / 1_000_000)
+    }
+}
+
+impl GpuBackend for HwmonBackend {
+    fn name(&self) -> &'static str {
+        "hwmon"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        let read_optional =
+            |input: &Option<PathBuf>| input.as_deref().and_then(|path| Self::read_temp(path).ok());
+
+        Ok(GpuSnapshot {
+            temp: Self::read_temp(&self.temp_input)?,
+            hotspot_temp: read_optional(&self.hotspot_input),
+            memory_temp: read_optional(&self.memory_input),
+            fan_speed: pwm_to_percent(read_value(&self.pwm_path()).unwrap_or(0)),
+            fan_speeds: Vec::new(),
+            power_draw: self.read_power_draw(),
+            utilization: self.read_utilization(),
+            graphics_clock: self.read_clock("freq1_input"),
+            memory_clock: self.read_clock("freq2_input"),
+            throttle_reasons: None,
+        })
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        // the tachometer paired with pwm1, absent on boards without one
+        read_value(&self.hwmon_dir.join("fan1_input"))
+            .ok()
+            .map(|rpm| vec![rpm])
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        read_value(&self.pwm_enable_path())
+            .ok()
+            .map(|mode| mode == PWM_ENABLE_MANUAL)
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        write_value(&self.pwm_enable_path(), PWM_ENABLE_MANUAL)?;
+        Ok(())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        write_value(&self.pwm_enable_path(), PWM_ENABLE_AUTO)?;
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        write_value(&self.pwm_path(), percent_to_pwm(speed))?;
+        Ok(())
+    }
+}
+
+/// Drives a `pwmN` channel of any hwmon device, such as a case fan header, from the
+/// temperature `reader` reports for a GPU
+pub struct PwmOutputBackend {
+    reader: Box<dyn GpuBackend>,
+    pwm_path: PathBuf,
+    enable_path: PathBuf,
+    fan_input: PathBuf,
+    /// `pwmN_enable` found at startup, chips use different values for their automatic modes
+    original_enable: u64,
+}
+
+impl PwmOutputBackend {
+    pub fn new(
+        sysfs_root: &Path,
+        device: &str,
+        channel: u8,
+        original_enable: Option<u64>,
+        reader: Box<dyn GpuBackend>,
+    ) -> Result<Self, HwmonError> {
+        let hwmon_dir = find_hwmon_by_name(sysfs_root, device)?;
+        let pwm_path = hwmon_dir.join(format!("pwm{}", channel));
+        if !pwm_path.exists() {
+            return Err(HwmonError::MissingPwm(pwm_path));
+        }
+        let enable_path = hwmon_dir.join(format!("pwm{}_enable", channel));
+
+        Ok(PwmOutputBackend {
+            reader,
+            pwm_path,
+            original_enable: match original_enable {
+                Some(mode) => mode,
+                None => read_value(&enable_path)?,
+            },
+            enable_path,
+            fan_input: hwmon_dir.join(format!("fan{}_input", channel)),
+        })
+    }
+}
+
+impl GpuBackend for PwmOutputBackend {
+    fn name(&self) -> &'static str {
+        "hwmon-pwm"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        let snapshot = self.reader.get_snapshot()?;
+        // the temperatures are the GPU's, the fan is this channel
+        Ok(GpuSnapshot {
+            fan_speed: pwm_to_percent(read_value(&self.pwm_path)?),
+            fan_speeds: Vec::new(),
+            ..snapshot
+        })
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        // the channel is read on its own, the GPU doesn't matter here
+        Ok(pwm_to_percent(read_value(&self.pwm_path)?))
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        read_value(&self.fan_input).ok().map(|rpm| vec![rpm])
+    }
+
+    fn original_pwm_enable(&self) -> Option<u64> {
+        Some(self.original_enable)
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        read_value(&self.enable_path)
+            .ok()
+            .map(|mode| mode == PWM_ENABLE_MANUAL)
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        write_value(&self.enable_path, PWM_ENABLE_MANUAL)?;
+        Ok(())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        // a channel that was already manual goes back to the chip's default automatic mode
+        let mode = match self.original_enable {
+            PWM_ENABLE_MANUAL => PWM_ENABLE_AUTO,
+            mode => mode,
+        };
+        write_value(&self.enable_path, mode)?;
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        write_value(&self.pwm_path, percent_to_pwm(speed))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+use crate::backend::GpuBackend;
+use crate::hwmon::{self, HwmonBackend, HwmonError, PwmOutputBackend};
+
+fn fake_card(root: &Path, card: u8, hwmon: &str) -> PathBuf {
+    let dir = root
+        .join("class/drm")
+        .join(format!("card{}", card))
+        .join("device/hwmon")
+        .join(hwmon);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("temp1_input"), "54000\n").unwrap();
+    fs::write(dir.join("temp2_input"), "61000\n").unwrap();
+    fs::write(dir.join("temp2_label"), "junction\n").unwrap();
+    fs::write(dir.join("temp3_input"), "66000\n").unwrap();
+    fs::write(dir.join("temp3_label"), "mem\n").unwrap();
+    fs::write(dir.join("temp10_input"), "70000\n").unwrap();
+    fs::write(dir.join("pwm1"), "128\n").unwrap();
+    fs::write(dir.join("pwm1_enable"), "2\n").unwrap();
+    fs::write(dir.join("fan1_input"), "1650\n").unwrap();
+    fs::write(dir.join("power1_average"), "142000000\n").unwrap();
+    fs::write(dir.join("freq1_input"), "2105000000\n").unwrap();
+    fs::write(dir.join("freq2_input"), "1250000000\n").unwrap();
+    fs::write(dir.join("../../gpu_busy_percent"), "37\n").unwrap();
+    dir
+}
+
+#[test]
+fn test_pwm_conversion() {
+    let cases = vec![(0, 0), (46, 117), (50, 128), (100, 255), (120, 255)];
+    for (percent, pwm) in cases {
+        assert_eq!(hwmon::percent_to_pwm(percent), pwm, "percent {}", percent);
+    }
+
+    let cases = vec![(0, 0), (117, 46), (128, 50), (255, 100), (300, 100)];
+    for (pwm, percent) in cases {
+        assert_eq!(hwmon::pwm_to_percent(pwm), percent, "pwm {}", pwm);
+    }
+}
+
+#[test]
+fn test_hwmon_backend() {
+    let temp_dir = TempDir::new().unwrap();
+    let hwmon_dir = fake_card(temp_dir.path(), 1, "hwmon3");
+
+    let mut backend = HwmonBackend::new(temp_dir.path(), 1).unwrap();
+    assert_eq!(backend.hwmon_dir, hwmon_dir);
+    let snapshot = backend.get_snapshot().unwrap();
+    assert_eq!(snapshot.temp, 54);
+    assert_eq!(snapshot.hotspot_temp, Some(61));
+    assert_eq!(snapshot.memory_temp, Some(66));
+    assert_eq!(snapshot.fan_speed, 50);
+    assert_eq!(snapshot.power_draw, Some(142.0));
+    assert_eq!(snapshot.utilization, Some(37));
+    assert_eq!(snapshot.graphics_clock, Some(2105));
+    assert_eq!(snapshot.memory_clock, Some(1250));
+    assert_eq!(snapshot.throttle_reasons, None);
+    assert_eq!(backend.get_fan_rpms(), Some(vec![1650]));
+
+    assert_eq!(backend.get_fan_control(), Some(false));
+    backend.acquire_fan_control().unwrap();
+    assert_eq!(backend.get_fan_control(), Some(true));
+    assert_eq!(
+        fs::read_to_string(hwmon_dir.join("pwm1_enable")).unwrap(),
+        "1"
+    );
+
+    backend.set_fan_speed(80).unwrap();
+    assert_eq!(fs::read_to_string(hwmon_dir.join("pwm1")).unwrap(), "204");
+    assert_eq!(backend.get_snapshot().unwrap().fan_speed, 80);
+
+    backend.release_fan_control().unwrap();
+    assert_eq!(
+        fs::read_to_string(hwmon_dir.join("pwm1_enable")).unwrap(),
+        "2"
+    );
+}
+
+#[test]
+fn test_hwmon_missing_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let result = HwmonBackend::new(temp_dir.path(), 0);
+    assert!(matches!(result, Err(HwmonError::MissingHwmon(_))));
+
+    // a hwmon directory without any temperature inputs
+    let hwmon_dir = temp_dir.path().join("class/drm/card0/device/hwmon/hwmon0");
+    fs::create_dir_all(&hwmon_dir).unwrap();
+    let result = HwmonBackend::new(temp_dir.path(), 0);
+    assert!(matches!(result, Err(HwmonError::MissingTempInput(_))));
+}
+
+#[test]
+fn test_pwm_output_backend() {
+    let temp_dir = TempDir::new().unwrap();
+    fake_card(temp_dir.path(), 0, "hwmon2");
+    let chip_dir = temp_dir.path().join("class/hwmon/hwmon4");
+    fs::create_dir_all(&chip_dir).unwrap();
+    fs::write(chip_dir.join("name"), "nct6798\n").unwrap();
+    fs::write(chip_dir.join("pwm2"), "64\n").unwrap();
+    // chips number their automatic modes differently
+    fs::write(chip_dir.join("pwm2_enable"), "5\n").unwrap();
+    fs::write(chip_dir.join("fan2_input"), "900\n").unwrap();
+    assert_eq!(
+        hwmon::find_hwmon_by_name(temp_dir.path(), "nct6798").unwrap(),
+        chip_dir
+    );
+
+    let reader = Box::new(HwmonBackend::new(temp_dir.path(), 0).unwrap());
+    let mut backend = PwmOutputBackend::new(temp_dir.path(), "nct6798", 2, None, reader).unwrap();
+    let snapshot = backend.get_snapshot().unwrap();
+    assert_eq!(snapshot.temp, 54, "The temperature is the GPU's");
+    assert_eq!(snapshot.fan_speed, 25, "The fan speed is the channel's");
+    assert_eq!(backend.get_fan_rpms(), Some(vec![900]));
+
+    assert_eq!(backend.get_fan_control(), Some(false));
+    backend.acquire_fan_control().unwrap();
+    assert_eq!(backend.get_fan_control(), Some(true));
+    backend.set_fan_speed(100).unwrap();
+    assert_eq!(fs::read_to_string(chip_dir.join("pwm2")).unwrap(), "255");
+
+    assert_eq!(backend.original_pwm_enable(), Some(5));
+    backend.release_fan_control().unwrap();
+    assert_eq!(
+        fs::read_to_string(chip_dir.join("pwm2_enable")).unwrap(),
+        "5"
+    );
+
+    // a backend rebuilt for cleanup only sees manual mode, the startup mode is passed in
+    backend.acquire_fan_control().unwrap();
+    let reader = Box::new(HwmonBackend::new(temp_dir.path(), 0).unwrap());
+    let mut rebuilt =
+        PwmOutputBackend::new(temp_dir.path(), "nct6798", 2, Some(5), reader).unwrap();
+    rebuilt.release_fan_control().unwrap();
+    assert_eq!(
+        fs::read_to_string(chip_dir.join("pwm2_enable")).unwrap(),
+        "5"
+    );
+}
+
+#[test]
+fn test_pwm_output_missing_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    fake_card(temp_dir.path(), 0, "hwmon0");
+    let reader = || Box::new(HwmonBackend::new(temp_dir.path(), 0).unwrap());
+
+    let result = PwmOutputBackend::new(temp_dir.path(), "nct6798", 1, None, reader());
+    assert!(matches!(result, Err(HwmonError::MissingHwmon(_))));
+
+    let chip_dir = temp_dir.path().join("class/hwmon/hwmon1");
+    fs::create_dir_all(&chip_dir).unwrap();
+    fs::write(chip_dir.join("name"), "it8689\n").unwrap();
+    let result = PwmOutputBackend::new(temp_dir.path(), "nct6798", 1, None, reader());
+    assert!(matches!(result, Err(HwmonError::UnknownDevice(_))));
+
+    let result = PwmOutputBackend::new(temp_dir.path(), "it8689", 3, None, reader());
+    assert!(matches!(result, Err(HwmonError::MissingPwm(_))));
+}
@@ -1,20 +1,50 @@
 use clap::Parser;
 use std::error::Error;
 use std::panic::catch_unwind;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
 
+mod backend;
 mod commands;
 mod config;
 mod filelock;
+mod helper;
+mod hwmon;
+mod nvml;
+mod pid;
+mod shared;
+mod sim;
+mod stream;
 mod thermalmanager;
+mod trace;
 
+#[cfg(test)]
+mod backend_test;
+#[cfg(test)]
+mod commands_test;
 #[cfg(test)]
 mod config_test;
 #[cfg(test)]
+mod helper_test;
+#[cfg(test)]
+mod hwmon_test;
+#[cfg(test)]
+mod nvml_test;
+#[cfg(test)]
+mod pid_test;
+#[cfg(test)]
+mod shared_test;
+#[cfg(test)]
+mod sim_test;
+#[cfg(test)]
+mod stream_test;
+#[cfg(test)]
 mod thermalmanager_test;
+#[cfg(test)]
+mod trace_test;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -22,95 +52,224 @@ pub struct Args {
     /// Path of the config file to load
     #[arg(short, long, value_name = "PATH")]
     file: Option<String>,
+
+    /// Record every sensor reading and commanded fan speed to a trace file
+    #[arg(long, value_name = "PATH")]
+    record: Option<String>,
+
+    /// Replay a recorded trace file instead of reading a GPU
+    #[arg(long, value_name = "PATH")]
+    replay: Option<String>,
+
+    /// Run as the root helper that applies fan writes for an unprivileged controller
+    #[arg(long)]
+    helper: bool,
 }
 
-fn cleanup(gpu_id: &u8) -> Result<(), Box<dyn Error>> {
-    println!("Attempting to gracefully shutdown...");
-    commands::set_fan_control(gpu_id, 0)?;
+fn cleanup(
+    backend: &mut dyn backend::GpuBackend,
+    config: &config::Config,
+    initial: &backend::InitialFanState,
+) -> Result<(), Box<dyn Error>> {
+    println!(
+        "Attempting to gracefully shutdown, leaving the fans in {:?} mode...",
+        config.exit_mode
+    );
+    backend::apply_exit_mode(backend, config.exit_mode, config.exit_speed, initial)?;
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let terminate = Arc::new(AtomicBool::new(false));
-    filelock::acquire_lock()?;
-
-    let config = Arc::new(RwLock::new(config::load_config_from_env(args.file)?));
-    let config_guard = config.read().unwrap();
-    let gpu_id = config_guard.gpu_id;
-    let global_delay = config_guard.global_delay;
-
+fn register_shutdown_signals(terminate: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
     // register common signals representing 'shutdown'
     for sig in &[
         signal_hook::consts::SIGTERM,
         signal_hook::consts::SIGINT,
         signal_hook::consts::SIGABRT,
     ] {
-        signal_hook::flag::register(*sig, Arc::clone(&terminate))?;
+        signal_hook::flag::register(*sig, Arc::clone(terminate))?;
     }
+    Ok(())
+}
 
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let terminate = Arc::new(AtomicBool::new(false));
+
+    if args.helper {
+        let helper_config = config::load_config_from_env(args.file)?;
+        register_shutdown_signals(&terminate)?;
+        return helper::run(&helper_config, &terminate);
+    }
+
+    filelock::acquire_lock()?;
+
+    let mut loaded_config = config::load_config_from_env(args.file)?;
+    if let Some(path) = args.replay {
+        loaded_config.backend = config::BackendKind::Replay;
+        loaded_config.replay_trace = Some(path);
+        if !loaded_config.gpus.is_empty() || !loaded_config.pwm_outputs.is_empty() {
+            println!("Replaying a trace ignores the [[gpu]] and [[pwm_output]] sections");
+            loaded_config.gpus.clear();
+            loaded_config.pwm_outputs.clear();
+        }
+    }
+
+    let targets = loaded_config.control_targets();
+    let multi_target = targets.len() > 1;
+    let mut gpu_backends = Vec::new();
+    for (target, mut gpu_backend) in targets.iter().zip(backend::for_targets(&targets)?) {
+        if let Some(path) = &args.record {
+            // one trace per control loop, a trace only describes a single set of fans
+            let path = match (&target.pwm_output, multi_target) {
+                (Some(output), _) => format!("{}.{}-pwm{}", path, output.hwmon, output.channel),
+                (None, true) => format!("{}.gpu{}", path, target.config.gpu_id),
+                (None, false) => path.clone(),
+            };
+            println!("Recording sensor trace to: {}", path);
+            gpu_backend = Box::new(trace::TraceRecorder::create(Path::new(&path), gpu_backend)?);
+        }
+        println!(
+            "Using {} backend with {} fan(s) for {}",
+            gpu_backend.name(),
+            gpu_backend.get_fan_count(),
+            target.label()
+        );
+        gpu_backends.push(gpu_backend);
+    }
+
+    register_shutdown_signals(&terminate)?;
+
+    let initial_states: Vec<backend::InitialFanState> = gpu_backends
+        .iter_mut()
+        .map(|gpu_backend| backend::InitialFanState::capture(gpu_backend.as_mut()))
+        .collect();
+    let panic_targets: Vec<_> = targets
+        .iter()
+        .cloned()
+        .zip(initial_states.iter().cloned())
+        .collect();
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
         eprintln!("Panic occurred: {:?}", panic_info);
         default_panic(panic_info);
-        // try to gracefully shutdown when panicing
-        if let Err(e) = cleanup(&gpu_id) {
-            eprintln!("Error during cleanup: {:?}", e);
+        // try to gracefully shutdown every GPU and PWM output when panicing
+        for (panic_target, panic_initial_state) in &panic_targets {
+            let result = backend::for_cleanup(panic_target, panic_initial_state)
+                .and_then(|mut b| cleanup(b.as_mut(), &panic_target.config, panic_initial_state));
+            if let Err(e) = result {
+                eprintln!("Error during cleanup: {:?}", e);
+            }
         }
         std::process::exit(1);
     }));
 
     // preemptively lock fan control for our use
-    commands::set_fan_control(&gpu_id, 1)?;
+    for index in 0..gpu_backends.len() {
+        if let Err(e) = gpu_backends[index].acquire_fan_control() {
+            eprintln!(
+                "Failed to take fan control of {}: {}",
+                targets[index].label(),
+                e
+            );
+            // hand back whatever was already taken before giving up
+            for ((gpu_backend, target), initial_state) in gpu_backends[..index]
+                .iter_mut()
+                .zip(&targets)
+                .zip(&initial_states)
+            {
+                if let Err(e) = cleanup(gpu_backend.as_mut(), &target.config, initial_state) {
+                    eprintln!("Error during cleanup of {}: {:?}", target.label(), e);
+                }
+            }
+            return Err(e);
+        }
+    }
 
-    let thermal_manager = {
-        let thermal_guard = match config.read() {
-            Ok(thermal_guard) => thermal_guard,
-            Err(err) => {
-                eprintln!("Thermal config lock poisoned: {}", err);
-                std::process::exit(1);
+    let thermal_managers: Vec<_> = targets
+        .iter()
+        .zip(gpu_backends)
+        .map(|(target, gpu_backend)| {
+            let mut manager =
+                thermalmanager::ThermalManager::new(target.config.clone(), gpu_backend);
+            if multi_target {
+                manager.label = format!("{}: ", target.label());
             }
-        };
+            Arc::new(RwLock::new(manager))
+        })
+        .collect();
+
+    let failed = Arc::new(AtomicBool::new(false));
+    let thermal_threads: Vec<_> = thermal_managers
+        .iter()
+        .map(|thermal_manager| {
+            let terminate = Arc::clone(&terminate);
+            let failed = Arc::clone(&failed);
+            let thermal_manager_lock = Arc::clone(thermal_manager);
+            let global_delay = thermal_manager.read().unwrap().config.global_delay;
 
-        Arc::new(RwLock::new(thermalmanager::ThermalManager::new(
-            thermal_guard.clone(),
-        )))
-    };
-
-    let thermal_thread = {
-        let terminate = Arc::clone(&terminate);
-        let thermal_manager_lock = Arc::clone(&thermal_manager);
-
-        thread::spawn(move || {
-            while !terminate.load(Ordering::SeqCst) {
-                if let Err(e) = catch_unwind(|| {
-                    if let Ok(mut manager) = thermal_manager_lock.write() {
-                        manager.update_temperature();
-                        if let Err(e) = manager.set_target_fan_speed() {
-                            eprintln!("Failed to set fan speed: {:?}", e);
-                            std::process::exit(1);
+            thread::spawn(move || {
+                while !terminate.load(Ordering::SeqCst) {
+                    let step = catch_unwind(|| {
+                        let mut manager = thermal_manager_lock.write().ok()?;
+                        if let Err(e) = manager.control_step() {
+                            eprintln!(
+                                "{}Giving up after {} failed fan writes in a row: {}",
+                                manager.label, manager.failed_writes, e
+                            );
+                            // the main thread restores every GPU before exiting
+                            failed.store(true, Ordering::SeqCst);
+                            terminate.store(true, Ordering::SeqCst);
                         }
-                    }
-                }) {
-                    eprintln!("Error in thermal thread: {:?}", e);
-                    break;
-                }
+                        manager.backend.next_delay()
+                    });
+                    let delay = match step {
+                        Ok(next) => next.unwrap_or(Duration::from_secs(global_delay)),
+                        Err(e) => {
+                            eprintln!("Error in thermal thread: {:?}", e);
+                            break;
+                        }
+                    };
 
-                // update the temperature/fan-speed every X seconds
-                thread::sleep(Duration::from_secs(global_delay));
-            }
+                    // update the temperature/fan-speed every X seconds, a replay at its recorded pace
+                    thread::sleep(delay);
+                }
+            })
         })
-    };
+        .collect();
 
     // watch for exit signal
     while !terminate.load(Ordering::SeqCst) {
         thread::sleep(Duration::from_millis(100));
     }
-    // try to gracefully shutdown
-    cleanup(&gpu_id)?;
-    if let Err(e) = thermal_thread.join() {
-        eprintln!("Thermal thread panicked: {:?}", e);
+    // try to gracefully shutdown, a failure on one GPU shouldn't leave the others behind
+    let mut cleanup_error = None;
+    for ((thermal_manager, target), initial_state) in
+        thermal_managers.iter().zip(&targets).zip(&initial_states)
+    {
+        let result = match thermal_manager.write() {
+            Ok(mut manager) => cleanup(manager.backend.as_mut(), &target.config, initial_state),
+            Err(err) => {
+                eprintln!("Thermal manager lock poisoned: {}", err);
+                backend::for_cleanup(target, initial_state)
+                    .and_then(|mut b| cleanup(b.as_mut(), &target.config, initial_state))
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Error during cleanup of {}: {:?}", target.label(), e);
+            cleanup_error.get_or_insert(e);
+        }
+    }
+    for thermal_thread in thermal_threads {
+        if let Err(e) = thermal_thread.join() {
+            eprintln!("Thermal thread panicked: {:?}", e);
+        }
     }
 
-    Ok(())
+    if failed.load(Ordering::SeqCst) {
+        std::process::exit(1);
+    }
+    match cleanup_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,491 @@
+use libloading::Library;
+use std::error::Error;
+use std::ffi::{c_char, c_int, c_uint, c_ulonglong, c_void, CStr};
+use std::fmt;
+
+use crate::backend::{GpuBackend, GpuSnapshot, NvidiaCliBackend};
+
+pub const DEFAULT_LIBRARY_NAMES: [&str; 2] = ["libnvidia-ml.so.1", "libnvidia-ml.so"];
+
+const NVML_SUCCESS: c_int = 0;
+const NVML_TEMPERATURE_GPU: c_int = 0;
+const NVML_CLOCK_GRAPHICS: c_int = 0;
+const NVML_CLOCK_MEM: c_int = 2;
+const NVML_FI_DEV_MEMORY_TEMP: c_uint = 82;
+const NVML_VALUE_TYPE_DOUBLE: c_int = 0;
+const NVML_VALUE_TYPE_UNSIGNED_INT: c_int = 1;
+const NVML_VALUE_TYPE_SIGNED_INT: c_int = 5;
+const NVML_FAN_POLICY_MANUAL: c_uint = 1;
+// NVML_STRUCT_VERSION(FanSpeedInfo, 1)
+const NVML_FAN_SPEED_INFO_V1: c_uint =
+    std::mem::size_of::<NvmlFanSpeedInfo>() as c_uint | (1 << 24);
+
+type NvmlReturn = c_int;
+type NvmlDevice = *mut c_void;
+
+#[repr(C)]
+#[derive(Default)]
+struct NvmlUtilization {
+    gpu: c_uint,
+    memory: c_uint,
+}
+
+/// `nvmlFanSpeedInfo_t`
+#[repr(C)]
+#[derive(Default)]
+struct NvmlFanSpeedInfo {
+    version: c_uint,
+    fan: c_uint,
+    speed: c_uint,
+}
+
+/// `nvmlFieldValue_t`, `value` holds the 8-byte `nvmlValue_t` union
+#[repr(C)]
+#[derive(Default)]
+struct NvmlFieldValue {
+    field_id: c_uint,
+    scope_id: c_uint,
+    timestamp: i64,
+    latency_usec: i64,
+    value_type: c_int,
+    nvml_return: NvmlReturn,
+    value: u64,
+}
+
+impl NvmlFieldValue {
+    fn as_u64(&self) -> Option<u64> {
+        match self.value_type {
+            NVML_VALUE_TYPE_DOUBLE => Some(f64::from_bits(self.value).max(0.0) as u64),
+            NVML_VALUE_TYPE_UNSIGNED_INT => Some(self.value as u32 as u64),
+            NVML_VALUE_TYPE_SIGNED_INT => u64::try_from(self.value as u32 as i32).ok(),
+            // unsigned long, unsigned long long and signed long long are all 64-bit here
+            2..=4 => u64::try_from(self.value as i64).ok(),
+            _ => None,
+        }
+    }
+}
+
+type InitFn = unsafe extern "C" fn() -> NvmlReturn;
+type ShutdownFn = unsafe extern "C" fn() -> NvmlReturn;
+type ErrorStringFn = unsafe extern "C" fn(NvmlReturn) -> *const c_char;
+type GetHandleByIndexFn = unsafe extern "C" fn(c_uint, *mut NvmlDevice) -> NvmlReturn;
+type GetTemperatureFn = unsafe extern "C" fn(NvmlDevice, c_int, *mut c_uint) -> NvmlReturn;
+type GetFanSpeedFn = unsafe extern "C" fn(NvmlDevice, *mut c_uint) -> NvmlReturn;
+type GetPowerUsageFn = unsafe extern "C" fn(NvmlDevice, *mut c_uint) -> NvmlReturn;
+type GetUtilizationRatesFn = unsafe extern "C" fn(NvmlDevice, *mut NvmlUtilization) -> NvmlReturn;
+type GetClockInfoFn = unsafe extern "C" fn(NvmlDevice, c_int, *mut c_uint) -> NvmlReturn;
+type GetThrottleReasonsFn = unsafe extern "C" fn(NvmlDevice, *mut c_ulonglong) -> NvmlReturn;
+type GetFanSpeedV2Fn = unsafe extern "C" fn(NvmlDevice, c_uint, *mut c_uint) -> NvmlReturn;
+type GetFanSpeedRpmFn = unsafe extern "C" fn(NvmlDevice, *mut NvmlFanSpeedInfo) -> NvmlReturn;
+type GetFanControlPolicyFn = unsafe extern "C" fn(NvmlDevice, c_uint, *mut c_uint) -> NvmlReturn;
+type GetNumFansFn = unsafe extern "C" fn(NvmlDevice, *mut c_uint) -> NvmlReturn;
+type GetFieldValuesFn = unsafe extern "C" fn(NvmlDevice, c_int, *mut NvmlFieldValue) -> NvmlReturn;
+type SetFanSpeedFn = unsafe extern "C" fn(NvmlDevice, c_uint, c_uint) -> NvmlReturn;
+type SetDefaultFanSpeedFn = unsafe extern "C" fn(NvmlDevice, c_uint) -> NvmlReturn;
+
+#[derive(Debug)]
+pub enum NvmlError {
+    Load(String),
+    MissingSymbol(&'static str),
+    Call(&'static str, NvmlReturn, String),
+}
+
+impl fmt::Display for NvmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NvmlError::Load(err) => write!(f, "Failed to load NVML: {}", err),
+            NvmlError::MissingSymbol(name) => write!(f, "NVML is missing symbol '{}'", name),
+            NvmlError::Call(name, code, msg) => {
+                write!(f, "{} failed with code {}: {}", name, code, msg)
+            }
+        }
+    }
+}
+impl std::error::Error for NvmlError {}
+
+/// Function table resolved from the NVML shared object
+struct NvmlApi {
+    shutdown: ShutdownFn,
+    error_string: Option<ErrorStringFn>,
+    get_temperature: GetTemperatureFn,
+    get_fan_speed: GetFanSpeedFn,
+    get_num_fans: Option<GetNumFansFn>,
+    get_fan_speed_v2: Option<GetFanSpeedV2Fn>,
+    // RPM readings only exist on 555+ drivers
+    get_fan_speed_rpm: Option<GetFanSpeedRpmFn>,
+    get_fan_control_policy: Option<GetFanControlPolicyFn>,
+    // extra telemetry is optional so older drivers still load
+    get_power_usage: Option<GetPowerUsageFn>,
+    get_utilization_rates: Option<GetUtilizationRatesFn>,
+    get_clock_info: Option<GetClockInfoFn>,
+    get_throttle_reasons: Option<GetThrottleReasonsFn>,
+    get_field_values: Option<GetFieldValuesFn>,
+    // fan-control setters only exist on 520+ drivers
+    set_fan_speed: Option<SetFanSpeedFn>,
+    set_default_fan_speed: Option<SetDefaultFanSpeedFn>,
+    // keeps the function pointers above valid
+    library: Library,
+}
+
+fn symbol<T: Copy>(library: &Library, name: &'static str) -> Option<T> {
+    let raw = format!("{}\0", name);
+    unsafe { library.get::<T>(raw.as_bytes()).ok().map(|s| *s) }
+}
+
+fn required<T: Copy>(library: &Library, name: &'static str) -> Result<T, NvmlError> {
+    symbol(library, name).ok_or(NvmlError::MissingSymbol(name))
+}
+
+impl NvmlApi {
+    fn open(path: &str) -> Result<Self, NvmlError> {
+        let library = unsafe { Library::new(path) }.map_err(|e| NvmlError::Load(e.to_string()))?;
+
+        Ok(NvmlApi {
+            shutdown: required(&library, "nvmlShutdown")?,
+            error_string: symbol(&library, "nvmlErrorString"),
+            get_temperature: required(&library, "nvmlDeviceGetTemperature")?,
+            get_fan_speed: required(&library, "nvmlDeviceGetFanSpeed")?,
+            get_num_fans: symbol(&library, "nvmlDeviceGetNumFans"),
+            get_fan_speed_v2: symbol(&library, "nvmlDeviceGetFanSpeed_v2"),
+            get_fan_speed_rpm: symbol(&library, "nvmlDeviceGetFanSpeedRPM"),
+            get_fan_control_policy: symbol(&library, "nvmlDeviceGetFanControlPolicy_v2"),
+            get_power_usage: symbol(&library, "nvmlDeviceGetPowerUsage"),
+            get_utilization_rates: symbol(&library, "nvmlDeviceGetUtilizationRates"),
+            get_clock_info: symbol(&library, "nvmlDeviceGetClockInfo"),
+            get_throttle_reasons: symbol(&library, "nvmlDeviceGetCurrentClocksThrottleReasons"),
+            get_field_values: symbol(&library, "nvmlDeviceGetFieldValues"),
+            set_fan_speed: symbol(&library, "nvmlDeviceSetFanSpeed_v2"),
+            set_default_fan_speed: symbol(&library, "nvmlDeviceSetDefaultFanSpeed_v2"),
+            library,
+        })
+    }
+
+    fn check(&self, name: &'static str, code: NvmlReturn) -> Result<(), NvmlError> {
+        if code == NVML_SUCCESS {
+            return Ok(());
+        }
+
+        let msg = match self.error_string {
+            Some(error_string) => unsafe {
+                let ptr = error_string(code);
+                if ptr.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+                }
+            },
+            None => String::new(),
+        };
+
+        Err(NvmlError::Call(name, code, msg))
+    }
+}
+
+/// Reads telemetry straight from libnvidia-ml.so instead of spawning nvidia-smi
+pub struct NvmlBackend {
+    api: NvmlApi,
+    device: NvmlDevice,
+    fan_count: u64,
+    // used for fan writes when NVML can't set fan speeds itself
+    cli_fallback: Option<NvidiaCliBackend>,
+}
+
+// NVML handles are thread-safe and only ever used behind the ThermalManager lock
+unsafe impl Send for NvmlBackend {}
+unsafe impl Sync for NvmlBackend {}
+
+impl NvmlBackend {
+    /// Loads NVML from `library_path`, or from the default sonames when `None`.
+    /// Fan writes go through NVML only when `privileged`, since the driver requires root,
+    /// otherwise they go through `cli`.
+    pub fn load(
+        library_path: Option<&str>,
+        gpu_id: u8,
+        privileged: bool,
+        cli: NvidiaCliBackend,
+    ) -> Result<Self, NvmlError> {
+        let api = match library_path {
+            Some(path) => NvmlApi::open(path)?,
+            None => {
+                let mut last_err = NvmlError::Load("no library candidates".to_string());
+                let mut loaded = None;
+                for name in DEFAULT_LIBRARY_NAMES {
+                    match NvmlApi::open(name) {
+                        Ok(api) => {
+                            loaded = Some(api);
+                            break;
+                        }
+                        Err(e) => last_err = e,
+                    }
+                }
+                loaded.ok_or(last_err)?
+            }
+        };
+
+        let library = &api.library;
+        let init: InitFn = required(library, "nvmlInit_v2")?;
+        let get_handle: GetHandleByIndexFn = required(library, "nvmlDeviceGetHandleByIndex_v2")?;
+
+        api.check("nvmlInit_v2", unsafe { init() })?;
+
+        let mut device: NvmlDevice = std::ptr::null_mut();
+        if let Err(e) = api.check("nvmlDeviceGetHandleByIndex_v2", unsafe {
+            get_handle(gpu_id as c_uint, &mut device)
+        }) {
+            unsafe { (api.shutdown)() };
+            return Err(e);
+        }
+
+        let mut backend = NvmlBackend {
+            api,
+            device,
+            fan_count: 1,
+            cli_fallback: None,
+        };
+        backend.fan_count = backend.query_fan_count().unwrap_or(1).max(1);
+
+        // NVML fan writes need root and a driver that exports the v2 setters
+        let can_write = privileged
+            && backend.api.set_fan_speed.is_some()
+            && backend.api.set_default_fan_speed.is_some();
+        if !can_write {
+            backend.cli_fallback = Some(cli);
+        }
+
+        Ok(backend)
+    }
+
+    pub fn writes_via_nvml(&self) -> bool {
+        self.cli_fallback.is_none()
+    }
+
+    fn query_fan_count(&self) -> Result<u64, NvmlError> {
+        let get_num_fans = self
+            .api
+            .get_num_fans
+            .ok_or(NvmlError::MissingSymbol("nvmlDeviceGetNumFans"))?;
+        let mut count: c_uint = 0;
+        self.api.check("nvmlDeviceGetNumFans", unsafe {
+            get_num_fans(self.device, &mut count)
+        })?;
+        Ok(count as u64)
+    }
+
+    fn query_temp(&self) -> Result<u64, NvmlError> {
+        let mut temp: c_uint = 0;
+        self.api.check("nvmlDeviceGetTemperature", unsafe {
+            (self.api.get_temperature)(self.device, NVML_TEMPERATURE_GPU, &mut temp)
+        })?;
+        Ok(temp as u64)
+    }
+
+    fn query_fan_speed(&self) -> Result<u64, NvmlError> {
+        let mut speed: c_uint = 0;
+        self.api.check("nvmlDeviceGetFanSpeed", unsafe {
+            (self.api.get_fan_speed)(self.device, &mut speed)
+        })?;
+        Ok(speed as u64)
+    }
+
+    fn query_fan_speeds(&self) -> Vec<u64> {
+        let Some(get_fan_speed_v2) = self.api.get_fan_speed_v2 else {
+            return Vec::new();
+        };
+
+        (0..self.fan_count)
+            .map_while(|fan| {
+                let mut speed: c_uint = 0;
+                let code = unsafe { get_fan_speed_v2(self.device, fan as c_uint, &mut speed) };
+                (code == NVML_SUCCESS).then_some((speed as u64).clamp(0, 100))
+            })
+            .collect()
+    }
+
+    fn query_fan_rpms(&self) -> Option<Vec<u64>> {
+        let get_fan_speed_rpm = self.api.get_fan_speed_rpm?;
+
+        (0..self.fan_count)
+            .map(|fan| {
+                let mut info = NvmlFanSpeedInfo {
+                    version: NVML_FAN_SPEED_INFO_V1,
+                    fan: fan as c_uint,
+                    speed: 0,
+                };
+                let code = unsafe { get_fan_speed_rpm(self.device, &mut info) };
+                (code == NVML_SUCCESS).then_some(info.speed as u64)
+            })
+            .collect()
+    }
+
+    fn query_manual_control(&self) -> Option<bool> {
+        let get_fan_control_policy = self.api.get_fan_control_policy?;
+
+        let policies = (0..self.fan_count)
+            .map(|fan| {
+                let mut policy: c_uint = 0;
+                let code =
+                    unsafe { get_fan_control_policy(self.device, fan as c_uint, &mut policy) };
+                (code == NVML_SUCCESS).then_some(policy)
+            })
+            .collect::<Option<Vec<c_uint>>>()?;
+        Some(
+            policies
+                .iter()
+                .all(|policy| *policy == NVML_FAN_POLICY_MANUAL),
+        )
+    }
+
+    fn write_fan_speed(&self, fan: u64, speed: u64) -> Result<(), NvmlError> {
+        let set_speed = self
+            .api
+            .set_fan_speed
+            .ok_or(NvmlError::MissingSymbol("nvmlDeviceSetFanSpeed_v2"))?;
+        self.api.check("nvmlDeviceSetFanSpeed_v2", unsafe {
+            set_speed(self.device, fan as c_uint, speed as c_uint)
+        })
+    }
+
+    fn query_power_draw(&self) -> Option<f64> {
+        let get_power_usage = self.api.get_power_usage?;
+        let mut milliwatts: c_uint = 0;
+        let code = unsafe { get_power_usage(self.device, &mut milliwatts) };
+        (code == NVML_SUCCESS).then_some(milliwatts as f64 / 1000.0)
+    }
+
+    fn query_utilization(&self) -> Option<u64> {
+        let get_utilization_rates = self.api.get_utilization_rates?;
+        let mut utilization = NvmlUtilization::default();
+        let code = unsafe { get_utilization_rates(self.device, &mut utilization) };
+        (code == NVML_SUCCESS).then_some(utilization.gpu as u64)
+    }
+
+    fn query_clock(&self, clock_type: c_int) -> Option<u64> {
+        let get_clock_info = self.api.get_clock_info?;
+        let mut clock: c_uint = 0;
+        let code = unsafe { get_clock_info(self.device, clock_type, &mut clock) };
+        (code == NVML_SUCCESS).then_some(clock as u64)
+    }
+
+    fn query_throttle_reasons(&self) -> Option<u64> {
+        let get_throttle_reasons = self.api.get_throttle_reasons?;
+        let mut reasons: c_ulonglong = 0;
+        let code = unsafe { get_throttle_reasons(self.device, &mut reasons) };
+        (code == NVML_SUCCESS).then_some(reasons)
+    }
+
+    fn query_memory_temp(&self) -> Option<u64> {
+        // only boards with memory junction sensors (HBM, GDDR6X) report this field
+        let get_field_values = self.api.get_field_values?;
+        let mut field = NvmlFieldValue {
+            field_id: NVML_FI_DEV_MEMORY_TEMP,
+            ..NvmlFieldValue::default()
+        };
+        let code = unsafe { get_field_values(self.device, 1, &mut field) };
+        if code != NVML_SUCCESS || field.nvml_return != NVML_SUCCESS {
+            return None;
+        }
+        field.as_u64().filter(|temp| *temp > 0)
+    }
+}
+
+impl Drop for NvmlBackend {
+    fn drop(&mut self) {
+        unsafe { (self.api.shutdown)() };
+    }
+}
+
+impl GpuBackend for NvmlBackend {
+    fn name(&self) -> &'static str {
+        "nvml"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        Ok(GpuSnapshot {
+            temp: self.query_temp()?.clamp(0, 200),
+            // NVML has no public hotspot sensor
+            hotspot_temp: None,
+            memory_temp: self.query_memory_temp().map(|temp| temp.clamp(0, 200)),
+            fan_speed: self.query_fan_speed().unwrap_or(0).clamp(0, 100),
+            fan_speeds: self.query_fan_speeds(),
+            power_draw: self.query_power_draw(),
+            utilization: self.query_utilization(),
+            graphics_clock: self.query_clock(NVML_CLOCK_GRAPHICS),
+            memory_clock: self.query_clock(NVML_CLOCK_MEM),
+            throttle_reasons: self.query_throttle_reasons(),
+        })
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.fan_count
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        match (self.query_fan_rpms(), self.cli_fallback.as_mut()) {
+            (None, Some(cli)) => cli.get_fan_rpms(),
+            (rpms, _) => rpms,
+        }
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        let speeds = self.query_fan_speeds();
+        match self.cli_fallback.as_mut() {
+            _ if speeds.len() as u64 == self.fan_count => Some(speeds),
+            Some(cli) => cli.get_fan_speeds(),
+            None => None,
+        }
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        match self.cli_fallback.as_mut() {
+            Some(cli) => cli.get_fan_control(),
+            None => self.query_manual_control(),
+        }
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.cli_fallback.as_mut() {
+            Some(cli) => cli.acquire_fan_control(),
+            // NVML switches a fan to manual control on its first speed write
+            None => Ok(()),
+        }
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(cli) = self.cli_fallback.as_mut() {
+            return cli.release_fan_control();
+        }
+
+        let set_default = self
+            .api
+            .set_default_fan_speed
+            .ok_or(NvmlError::MissingSymbol("nvmlDeviceSetDefaultFanSpeed_v2"))?;
+        for fan in 0..self.fan_count {
+            self.api.check("nvmlDeviceSetDefaultFanSpeed_v2", unsafe {
+                set_default(self.device, fan as c_uint)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        if let Some(cli) = self.cli_fallback.as_mut() {
+            return cli.set_fan_speed(speed);
+        }
+
+        for fan in 0..self.fan_count {
+            self.write_fan_speed(fan, speed)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        if let Some(cli) = self.cli_fallback.as_mut() {
+            return cli.set_fan_speed_of(fan, speed);
+        }
+
+        self.write_fan_speed(fan, speed)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use libloading::Library;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+use crate::backend::{GpuBackend, NvidiaCliBackend};
+use crate::commands::SettingsContext;
+use crate::config::{Config, Escalation};
+use crate::nvml::{NvmlBackend, NvmlError};
+
+// Minimal stand-in for libnvidia-ml.so with a two-fan GPU at index 0
+const STUB_SOURCE: &str = r#"
+static int initialized = 0;
+static unsigned int fan_speeds[2] = { 40, 40 };
+static int manual[2] = { 0, 0 };
+
+int nvmlInit_v2(void) { initialized = 1; return 0; }
+int nvmlShutdown(void) { initialized = 0; return 0; }
+const char *nvmlErrorString(int result) { return result == 2 ? "Invalid Argument" : "Unknown Error"; }
+
+int nvmlDeviceGetHandleByIndex_v2(unsigned int index, void **device) {
+    if (!initialized) return 1;
+    if (index != 0) return 2;
+    *device = (void *)fan_speeds;
+    return 0;
+}
+int nvmlDeviceGetTemperature(void *device, int sensor, unsigned int *temp) {
+    if (sensor != 0) return 2;
+    *temp = 63;
+    return 0;
+}
+int nvmlDeviceGetFanSpeed(void *device, unsigned int *speed) { *speed = fan_speeds[0]; return 0; }
+int nvmlDeviceGetFanSpeed_v2(void *device, unsigned int fan, unsigned int *speed) {
+    if (fan > 1) return 2;
+    *speed = fan_speeds[fan];
+    return 0;
+}
+struct fan_speed_info { unsigned int version; unsigned int fan; unsigned int speed; };
+int nvmlDeviceGetFanSpeedRPM(void *device, struct fan_speed_info *info) {
+    if (info->version != (sizeof(struct fan_speed_info) | (1 << 24)) || info->fan > 1) return 2;
+    info->speed = fan_speeds[info->fan] * 30;
+    return 0;
+}
+int nvmlDeviceGetFanControlPolicy_v2(void *device, unsigned int fan, unsigned int *policy) {
+    if (fan > 1) return 2;
+    *policy = manual[fan];
+    return 0;
+}
+int nvmlDeviceGetNumFans(void *device, unsigned int *count) { *count = 2; return 0; }
+int nvmlDeviceSetFanSpeed_v2(void *device, unsigned int fan, unsigned int speed) {
+    if (fan > 1 || speed > 100) return 2;
+    fan_speeds[fan] = speed;
+    manual[fan] = 1;
+    return 0;
+}
+int nvmlDeviceSetDefaultFanSpeed_v2(void *device, unsigned int fan) {
+    if (fan > 1) return 2;
+    manual[fan] = 0;
+    return 0;
+}
+
+int nvmlDeviceGetPowerUsage(void *device, unsigned int *milliwatts) { *milliwatts = 187250; return 0; }
+int nvmlDeviceGetClockInfo(void *device, int type, unsigned int *clock) {
+    if (type == 0) { *clock = 1830; return 0; }
+    if (type == 2) { *clock = 9501; return 0; }
+    return 2;
+}
+int nvmlDeviceGetCurrentClocksThrottleReasons(void *device, unsigned long long *reasons) {
+    *reasons = 0x4;
+    return 0;
+}
+
+struct field_value {
+    unsigned int field_id;
+    unsigned int scope_id;
+    long long timestamp;
+    long long latency_usec;
+    int value_type;
+    int nvml_return;
+    unsigned long long value;
+};
+int nvmlDeviceGetFieldValues(void *device, int count, struct field_value *values) {
+    for (int i = 0; i < count; i++) {
+        if (values[i].field_id == 82) {
+            values[i].value_type = 1;
+            values[i].nvml_return = 0;
+            values[i].value = 78;
+        } else {
+            values[i].nvml_return = 2;
+        }
+    }
+    return 0;
+}
+
+unsigned int stub_fan_speed(unsigned int fan) { return fan_speeds[fan]; }
+int stub_manual(unsigned int fan) { return manual[fan]; }
+"#;
+
+fn cli() -> NvidiaCliBackend {
+    NvidiaCliBackend::new(
+        0,
+        SettingsContext {
+            escalation: Escalation::None,
+            ..SettingsContext::from_config(&Config::default())
+        },
+    )
+}
+
+fn build_stub(dir: &Path) -> PathBuf {
+    let source = dir.join("nvml_stub.c");
+    let library = dir.join("libnvidia-ml-stub.so");
+    fs::write(&source, STUB_SOURCE).unwrap();
+
+    let status = Command::new("cc")
+        .args(["-shared", "-fPIC", "-o"])
+        .arg(&library)
+        .arg(&source)
+        .status()
+        .expect("Failed to execute cc");
+    assert!(status.success(), "Failed to build the NVML stub");
+
+    library
+}
+
+#[test]
+fn test_nvml_stub_backend() {
+    let temp_dir = TempDir::new().unwrap();
+    let stub_path = build_stub(temp_dir.path());
+    let stub_str = stub_path.to_str().unwrap();
+
+    let mut backend = NvmlBackend::load(Some(stub_str), 0, true, cli()).unwrap();
+    assert!(backend.writes_via_nvml());
+    assert_eq!(backend.get_fan_count(), 2);
+    let snapshot = backend.get_snapshot().unwrap();
+    assert_eq!(snapshot.temp, 63);
+    assert_eq!(snapshot.memory_temp, Some(78));
+    assert_eq!(snapshot.hotspot_temp, None);
+    assert_eq!(snapshot.fan_speed, 40);
+    assert_eq!(snapshot.power_draw, Some(187.25));
+    assert_eq!(snapshot.graphics_clock, Some(1830));
+    assert_eq!(snapshot.memory_clock, Some(9501));
+    assert_eq!(snapshot.throttle_reasons, Some(0x4));
+    // the stub doesn't export nvmlDeviceGetUtilizationRates
+    assert_eq!(snapshot.utilization, None);
+    assert_eq!(backend.get_fan_rpms(), Some(vec![1200, 1200]));
+
+    backend.acquire_fan_control().unwrap();
+    backend.set_fan_speed(72).unwrap();
+    assert_eq!(backend.get_snapshot().unwrap().fan_speed, 72);
+
+    // inspect the stub state through a second handle to the same object
+    let stub = unsafe { Library::new(stub_str) }.unwrap();
+    let fan_speed: libloading::Symbol<unsafe extern "C" fn(u32) -> u32> =
+        unsafe { stub.get(b"stub_fan_speed\0") }.unwrap();
+    let manual: libloading::Symbol<unsafe extern "C" fn(u32) -> i32> =
+        unsafe { stub.get(b"stub_manual\0") }.unwrap();
+    assert_eq!(unsafe { fan_speed(1) }, 72, "Every fan should be written");
+    assert_eq!(unsafe { manual(1) }, 1);
+    assert_eq!(backend.get_fan_control(), Some(true));
+
+    backend.set_fan_speed_of(1, 58).unwrap();
+    assert_eq!(backend.get_snapshot().unwrap().fan_speeds, vec![72, 58]);
+
+    backend.release_fan_control().unwrap();
+    assert_eq!(unsafe { manual(0) }, 0);
+    assert_eq!(unsafe { manual(1) }, 0);
+    assert_eq!(backend.get_fan_control(), Some(false));
+}
+
+#[test]
+fn test_nvml_unprivileged_uses_cli_for_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let stub_path = build_stub(temp_dir.path());
+
+    let mut backend = NvmlBackend::load(stub_path.to_str(), 0, false, cli()).unwrap();
+    assert!(!backend.writes_via_nvml());
+    assert_eq!(backend.get_snapshot().unwrap().temp, 63);
+}
+
+#[test]
+fn test_nvml_load_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let missing = temp_dir.path().join("libnvidia-ml.so.missing");
+    let result = NvmlBackend::load(missing.to_str(), 0, true, cli());
+    assert!(matches!(result, Err(NvmlError::Load(_))));
+
+    let stub_path = build_stub(temp_dir.path());
+    let result = NvmlBackend::load(stub_path.to_str(), 3, true, cli());
+    match result {
+        Err(NvmlError::Call(name, code, msg)) => {
+            assert_eq!(name, "nvmlDeviceGetHandleByIndex_v2");
+            assert_eq!(code, 2);
+            assert_eq!(msg, "Invalid Argument");
+        }
+        _ => panic!("Expected an invalid device index error"),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::Config;
+
+/// PID loop turning the distance from a target temperature into a fan speed
+#[derive(Debug, Clone, PartialEq)]
+pub struct PidController {
+    pub target_temp: f64,
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    /// Share of the previous derivative kept each update, smooths out sensor steps
+    pub derivative_filter: f64,
+    /// Integral term in percent, `None` until the first update seeds it
+    integral: Option<f64>,
+    last_temp: Option<f64>,
+    derivative: f64,
+}
+
+impl PidController {
+    pub fn from_config(config: &Config) -> Self {
+        PidController {
+            target_temp: config.pid_target_temp as f64,
+            kp: config.pid_kp,
+            ki: config.pid_ki,
+            kd: config.pid_kd,
+            derivative_filter: config.pid_derivative_filter,
+            integral: None,
+            last_temp: None,
+            derivative: 0.0,
+        }
+    }
+
+    /// Forgets the accumulated state, the next update starts over from the current speed
+    pub fn reset(&mut self) {
+        self.integral = None;
+        self.last_temp = None;
+        self.derivative = 0.0;
+    }
+
+    /// Advances the loop by `dt` seconds and returns the fan speed clamped to `floor..=ceiling`,
+    /// the first update seeds the integral with `current_speed` so taking over doesn't jump
+    pub fn update(
+        &mut self,
+        temp: f64,
+        dt: f64,
+        current_speed: u64,
+        floor: u64,
+        ceiling: u64,
+    ) -> u64 {
+        let floor = floor as f64;
+        let ceiling = (ceiling as f64).max(floor);
+        let error = temp - self.target_temp;
+
+        // derivative on the measurement, so moving the target doesn't kick the fans
+        let raw_derivative = match self.last_temp {
+            Some(last) if dt > 0.0 => (temp - last) / dt,
+            _ => 0.0,
+        };
+        self.last_temp = Some(temp);
+        self.derivative = self.derivative_filter * self.derivative
+            + (1.0 - self.derivative_filter) * raw_derivative;
+
+        let proportional = self.kp * error;
+        let damping = self.kd * self.derivative;
+        let integral = *self
+            .integral
+            .get_or_insert((current_speed as f64).clamp(floor, ceiling));
+        let candidate = integral + self.ki * error * dt;
+
+        // anti-windup: only integrate until the output reaches a limit, never past it
+        let others = proportional + damping;
+        let integral = if others + candidate > ceiling && error > 0.0 {
+            integral.max(ceiling - others).min(candidate)
+        } else if others + candidate < floor && error < 0.0 {
+            integral.min(floor - others).max(candidate)
+        } else {
+            candidate
+        }
+        .clamp(floor, ceiling);
+        self.integral = Some(integral);
+
+        (others + integral).clamp(floor, ceiling).round() as u64
+    }
+}
@@ -0,0 +1,120 @@
+use crate::backend::GpuBackend;
+use crate::config::{Config, ControlMode, HeatLoad, SimConfig};
+use crate::pid::PidController;
+use crate::sim::SimBackend;
+use crate::thermalmanager::ThermalManager;
+
+fn controller(kp: f64, ki: f64, kd: f64, derivative_filter: f64) -> PidController {
+    PidController::from_config(&Config {
+        pid_target_temp: 70,
+        pid_kp: kp,
+        pid_ki: ki,
+        pid_kd: kd,
+        pid_derivative_filter: derivative_filter,
+        ..Config::default()
+    })
+}
+
+#[test]
+fn test_pid_seeds_from_current_speed() {
+    let mut pid = controller(4.0, 0.2, 0.0, 0.0);
+    // at the target nothing pushes the fans away from where they already are
+    assert_eq!(pid.update(70.0, 2.0, 55, 30, 100), 55);
+    assert_eq!(pid.update(70.0, 2.0, 0, 30, 100), 55);
+
+    // too hot raises the speed right away and keeps raising it while the error lasts
+    let first = pid.update(75.0, 2.0, 0, 30, 100);
+    let second = pid.update(75.0, 2.0, 0, 30, 100);
+    assert!(first > 55, "{}", first);
+    assert!(second > first, "{} then {}", first, second);
+
+    pid.reset();
+    assert_eq!(pid.update(70.0, 2.0, 10, 30, 100), 30, "Seeds are clamped");
+}
+
+#[test]
+fn test_pid_output_clamping() {
+    let mut pid = controller(4.0, 0.2, 0.0, 0.0);
+    assert_eq!(pid.update(95.0, 2.0, 60, 30, 80), 80);
+    assert_eq!(pid.update(40.0, 2.0, 60, 30, 80), 30);
+}
+
+#[test]
+fn test_pid_anti_windup() {
+    let mut pid = controller(2.0, 0.5, 0.0, 0.0);
+    pid.update(70.0, 2.0, 50, 30, 100);
+    // a long stretch pinned at the ceiling mustn't wind the integral past it
+    for _ in 0..200 {
+        assert_eq!(pid.update(90.0, 2.0, 0, 30, 100), 100);
+    }
+    // so the fans come off the ceiling as soon as the GPU is below the target
+    assert!(pid.update(68.0, 2.0, 0, 30, 100) < 100);
+
+    for _ in 0..200 {
+        pid.update(50.0, 2.0, 0, 30, 100);
+    }
+    assert!(pid.update(72.0, 2.0, 0, 30, 100) > 30);
+}
+
+#[test]
+fn test_pid_derivative_filter() {
+    let mut unfiltered = controller(0.0, 0.0, 10.0, 0.0);
+    let mut filtered = controller(0.0, 0.0, 10.0, 0.8);
+    for pid in [&mut unfiltered, &mut filtered] {
+        pid.update(70.0, 2.0, 50, 0, 100);
+    }
+
+    // a one degree step in the average kicks the unfiltered loop much harder
+    let raw = unfiltered.update(71.0, 2.0, 0, 0, 100);
+    let smoothed = filtered.update(71.0, 2.0, 0, 0, 100);
+    assert_eq!(raw, 55);
+    assert_eq!(smoothed, 51);
+    // and the kick fades out over a few steps instead of vanishing at once
+    assert_eq!(unfiltered.update(71.0, 2.0, 0, 0, 100), 50);
+    assert_eq!(filtered.update(71.0, 2.0, 0, 0, 100), 51);
+}
+
+#[test]
+fn test_pid_holds_target_temp() {
+    let mut gpu = SimBackend::new(SimConfig {
+        heat_load: HeatLoad::Constant { watts: 250.0 },
+        time_step: Some(2.0),
+        ..SimConfig::default()
+    });
+    gpu.acquire_fan_control().unwrap();
+    let mut pid = controller(4.0, 0.2, 10.0, 0.7);
+
+    let mut speed = 0;
+    for _ in 0..600 {
+        let temp = gpu.get_snapshot().unwrap().temp;
+        speed = pid.update(temp as f64, 2.0, speed, 30, 100);
+        gpu.set_fan_speed(speed).unwrap();
+    }
+    let temp = gpu.get_snapshot().unwrap().temp;
+    assert!(temp.abs_diff(70) <= 1, "Settled at {} C", temp);
+    // 250 W over 40 C needs 6.25 W/C, which the model reaches at ~53 %
+    assert!(speed.abs_diff(53) <= 3, "Settled at {} %", speed);
+}
+
+#[test]
+fn test_pid_mode_in_manager() {
+    let config = Config {
+        mode: ControlMode::Pid,
+        pid_target_temp: 70,
+        ..Config::default()
+    };
+    let mut manager = ThermalManager::new(
+        config.clone(),
+        Box::new(SimBackend::new(SimConfig::default())),
+    );
+    manager.current_fan_speed = 60;
+    manager.temp_average = 70;
+    assert_eq!(manager.get_target_fan_speed(), 60);
+
+    manager.temp_average = 40;
+    assert_eq!(
+        manager.get_target_fan_speed(),
+        config.fan_speed_floor,
+        "Well below the target the fans sit at the floor"
+    );
+}
@@ -0,0 +1,273 @@
+use std::error::Error;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::{GpuBackend, GpuSnapshot};
+use crate::config::{TempCombine, TempSource};
+
+/// Combines readings of several GPUs into the temperature of one set of fans
+pub fn combine_temps(combine: TempCombine, readings: &[(u64, f64)]) -> Option<u64> {
+    match combine {
+        TempCombine::Max => readings.iter().map(|&(temp, _)| temp).max(),
+        TempCombine::Weighted => {
+            let total_weight: f64 = readings.iter().map(|&(_, weight)| weight).sum();
+            if total_weight <= 0.0 {
+                return None;
+            }
+            let weighted: f64 = readings
+                .iter()
+                .map(|&(temp, weight)| temp as f64 * weight)
+                .sum();
+            Some((weighted / total_weight).round() as u64)
+        }
+    }
+}
+
+/// Drives the fans of `inner` from the combined temperatures of `sources`, so a shared
+/// blower follows every GPU it cools through the usual curve
+pub struct SharedTempBackend {
+    inner: Box<dyn GpuBackend>,
+    gpu_id: u8,
+    sources: Vec<TempSource>,
+    combine: TempCombine,
+    /// Read-only backends of the other GPUs listed in `sources`
+    readers: Vec<(u8, Box<dyn GpuBackend>)>,
+}
+
+impl SharedTempBackend {
+    pub fn new(
+        inner: Box<dyn GpuBackend>,
+        gpu_id: u8,
+        sources: Vec<TempSource>,
+        combine: TempCombine,
+        readers: Vec<(u8, Box<dyn GpuBackend>)>,
+    ) -> Self {
+        SharedTempBackend {
+            inner,
+            gpu_id,
+            sources,
+            combine,
+            readers,
+        }
+    }
+}
+
+impl GpuBackend for SharedTempBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        let mut snapshot = self.inner.get_snapshot()?;
+        // any unreadable source counts as a failed read, the fans can't follow a missing GPU
+        let mut others = Vec::with_capacity(self.readers.len());
+        for (gpu_id, reader) in self.readers.iter_mut() {
+            others.push((*gpu_id, reader.get_snapshot()?));
+        }
+
+        let readings: Vec<(u64, f64)> = self
+            .sources
+            .iter()
+            .filter_map(|source| {
+                let reading = if source.gpu_id == self.gpu_id {
+                    &snapshot
+                } else {
+                    &others.iter().find(|(id, _)| *id == source.gpu_id)?.1
+                };
+                // sensors a GPU doesn't report fall back to its core temperature
+                let temp = reading.sensor_temp(source.sensor).unwrap_or(reading.temp);
+                Some((temp, source.weight))
+            })
+            .collect();
+
+        snapshot.temp = combine_temps(self.combine, &readings).ok_or("No usable temp_sources")?;
+        // the combined value is the only temperature the curve should see
+        snapshot.hotspot_temp = None;
+        snapshot.memory_temp = None;
+        Ok(snapshot)
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        // the fans are only inner's, the other GPUs needn't be read
+        self.inner.read_fan_speed()
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.inner.get_fan_count()
+    }
+
+    fn next_delay(&self) -> Option<Duration> {
+        self.inner.next_delay()
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_rpms()
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_speeds()
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        self.inner.get_fan_control()
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.acquire_fan_control()
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.release_fan_control()
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.inner.set_fan_speed(speed)
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.inner.set_fan_speed_of(fan, speed)
+    }
+}
+
+const FEED_READ_ONLY: &str = "A GPU feed only reads, its fans belong to the GPU's own loop";
+
+/// When a snapshot was read along with the snapshot itself
+type Published = Mutex<Option<(Instant, GpuSnapshot)>>;
+
+/// Latest snapshot a GPU's control loop read, shared with the PWM outputs following it
+#[derive(Clone, Default)]
+pub struct SnapshotFeed(Arc<(Published, Condvar)>);
+
+impl SnapshotFeed {
+    pub fn publish(&self, snapshot: &GpuSnapshot) {
+        let (latest, published) = &*self.0;
+        if let Ok(mut latest) = latest.lock() {
+            *latest = Some((Instant::now(), snapshot.clone()));
+            published.notify_all();
+        }
+    }
+
+    /// The latest snapshot unless it's older than `max_age`, waiting up to `max_age` for the
+    /// first one so a loop started alongside the GPU's doesn't count a failed read
+    pub fn latest(&self, max_age: Duration) -> Option<GpuSnapshot> {
+        let (latest, published) = &*self.0;
+        let latest = latest.lock().ok()?;
+        let (latest, _) = published
+            .wait_timeout_while(latest, max_age, |latest| latest.is_none())
+            .ok()?;
+        latest
+            .as_ref()
+            .filter(|(read_at, _)| read_at.elapsed() <= max_age)
+            .map(|(_, snapshot)| snapshot.clone())
+    }
+}
+
+/// Passes everything through to `inner` and publishes each snapshot it reads
+pub struct FeedPublisher {
+    inner: Box<dyn GpuBackend>,
+    feed: SnapshotFeed,
+}
+
+impl FeedPublisher {
+    pub fn new(inner: Box<dyn GpuBackend>, feed: SnapshotFeed) -> Self {
+        FeedPublisher { inner, feed }
+    }
+}
+
+impl GpuBackend for FeedPublisher {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        let snapshot = self.inner.get_snapshot()?;
+        self.feed.publish(&snapshot);
+        Ok(snapshot)
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        self.inner.read_fan_speed()
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.inner.get_fan_count()
+    }
+
+    fn next_delay(&self) -> Option<Duration> {
+        self.inner.next_delay()
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_rpms()
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_speeds()
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        self.inner.get_fan_control()
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.acquire_fan_control()
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.release_fan_control()
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.inner.set_fan_speed(speed)
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.inner.set_fan_speed_of(fan, speed)
+    }
+}
+
+/// Reads a GPU through the snapshots its own control loop publishes instead of polling it
+/// again; a feed that has gone stale counts as a failed read
+pub struct FeedReader {
+    gpu_id: u8,
+    feed: SnapshotFeed,
+    max_age: Duration,
+}
+
+impl FeedReader {
+    pub fn new(gpu_id: u8, feed: SnapshotFeed, max_age: Duration) -> Self {
+        FeedReader {
+            gpu_id,
+            feed,
+            max_age,
+        }
+    }
+}
+
+impl GpuBackend for FeedReader {
+    fn name(&self) -> &'static str {
+        "feed"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        self.feed.latest(self.max_age).ok_or_else(|| {
+            format!(
+                "GPU {} has no reading from the last {:?}",
+                self.gpu_id, self.max_age
+            )
+            .into()
+        })
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Err(FEED_READ_ONLY.into())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Err(FEED_READ_ONLY.into())
+    }
+
+    fn set_fan_speed(&mut self, _speed: u64) -> Result<(), Box<dyn Error>> {
+        Err(FEED_READ_ONLY.into())
+    }
+}
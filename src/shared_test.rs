@@ -0,0 +1,142 @@
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+
+use crate::backend::{self, GpuBackend, GpuSnapshot};
+use crate::config::{
+    BackendKind, Config, HeatLoad, PwmOutputConfig, Sensor, SimConfig, TempCombine, TempSource,
+};
+use crate::shared::{self, FeedPublisher, FeedReader, SharedTempBackend, SnapshotFeed};
+use crate::sim::SimBackend;
+use crate::thermalmanager::ThermalManager;
+
+// a GPU that holds `temp` no matter what the fans do
+fn steady_gpu(temp: f64) -> Box<dyn GpuBackend> {
+    Box::new(SimBackend::new(SimConfig {
+        ambient_temp: temp,
+        initial_temp: temp,
+        heat_load: HeatLoad::Constant { watts: 0.0 },
+        time_step: Some(1.0),
+        ..SimConfig::default()
+    }))
+}
+
+fn source(gpu_id: u8, weight: f64) -> TempSource {
+    TempSource {
+        gpu_id,
+        sensor: Sensor::Core,
+        weight,
+    }
+}
+
+#[test]
+fn test_combine_temps() {
+    let readings = vec![(60, 1.0), (80, 3.0)];
+    assert_eq!(shared::combine_temps(TempCombine::Max, &readings), Some(80));
+    assert_eq!(
+        shared::combine_temps(TempCombine::Weighted, &readings),
+        Some(75)
+    );
+    assert_eq!(shared::combine_temps(TempCombine::Max, &[]), None);
+    assert_eq!(
+        shared::combine_temps(TempCombine::Weighted, &[(60, 0.0)]),
+        None
+    );
+}
+
+#[test]
+fn test_shared_fan_follows_hottest_gpu() {
+    let backend = SharedTempBackend::new(
+        steady_gpu(50.0),
+        0,
+        vec![source(0, 1.0), source(1, 1.0)],
+        TempCombine::Max,
+        vec![(1, steady_gpu(80.0))],
+    );
+    let config = Config {
+        smooth_mode: false,
+        ..Config::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+    thermal_manager.backend.acquire_fan_control().unwrap();
+
+    thermal_manager.control_step().unwrap();
+    assert_eq!(thermal_manager.current_temp, 80);
+    // the second GPU's temperature drives the first GPU's fans along the main curve
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        80
+    );
+}
+
+#[test]
+fn test_shared_backend_from_config() {
+    let config = Config {
+        backend: BackendKind::Sim,
+        temp_sources: vec![source(0, 1.0), source(1, 3.0)],
+        temp_combine: TempCombine::Weighted,
+        ..Config::default()
+    };
+    let mut backend = backend::from_config(&config).unwrap();
+    assert_eq!(backend.name(), "sim");
+    // both simulated GPUs share a config, so the weighting can't move the reading
+    assert_eq!(backend.get_snapshot().unwrap().temp, 30);
+}
+
+#[test]
+fn test_feed_shares_snapshots() {
+    let feed = SnapshotFeed::default();
+    let mut reader = FeedReader::new(0, feed.clone(), Duration::from_millis(50));
+    assert!(
+        reader.get_snapshot().is_err(),
+        "Nothing was published in time"
+    );
+    assert!(reader.set_fan_speed(50).is_err());
+
+    let mut publisher = FeedPublisher::new(steady_gpu(65.0), feed.clone());
+    assert_eq!(publisher.get_snapshot().unwrap().temp, 65);
+    assert_eq!(reader.get_snapshot().unwrap().temp, 65);
+
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(
+        reader.get_snapshot().is_err(),
+        "A stale snapshot counts as a failed read"
+    );
+    feed.publish(&GpuSnapshot {
+        temp: 70,
+        ..GpuSnapshot::default()
+    });
+    assert_eq!(reader.get_snapshot().unwrap().temp, 70);
+}
+
+#[test]
+fn test_pwm_output_reads_its_gpu_loop() {
+    let temp_dir = TempDir::new().unwrap();
+    let chip_dir = temp_dir.path().join("class/hwmon/hwmon3");
+    fs::create_dir_all(&chip_dir).unwrap();
+    fs::write(chip_dir.join("name"), "nct6798\n").unwrap();
+    fs::write(chip_dir.join("pwm1"), "128\n").unwrap();
+    fs::write(chip_dir.join("pwm1_enable"), "2\n").unwrap();
+    let config = Config {
+        backend: BackendKind::Sim,
+        sysfs_root: temp_dir.path().to_string_lossy().into_owned(),
+        pwm_outputs: vec![PwmOutputConfig {
+            hwmon: "nct6798".to_string(),
+            channel: 1,
+            ..PwmOutputConfig::default()
+        }],
+        ..Config::default()
+    };
+
+    let targets = config.control_targets();
+    let mut backends = backend::for_targets(&targets).unwrap();
+    assert_eq!(backends.len(), 2);
+    let gpu_snapshot = backends[0].get_snapshot().unwrap();
+    let output_snapshot = backends[1].get_snapshot().unwrap();
+    // the output sees the GPU loop's reading instead of stepping the GPU itself
+    assert_eq!(output_snapshot.temp, gpu_snapshot.temp);
+    assert_eq!(
+        output_snapshot.fan_speed, 50,
+        "The fan speed is the channel's"
+    );
+}
@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::time::Instant;
+
+use crate::backend::{GpuBackend, GpuSnapshot};
+use crate::config::{HeatLoad, SimConfig};
+
+impl HeatLoad {
+    pub fn watts_at(&self, elapsed: f64) -> f64 {
+        match self {
+            HeatLoad::Constant { watts } => *watts,
+            HeatLoad::Step { before, after, at } => {
+                if elapsed < *at {
+                    *before
+                } else {
+                    *after
+                }
+            }
+            HeatLoad::Script { points } => points
+                .iter()
+                .take_while(|(time, _)| *time <= elapsed)
+                .last()
+                .or(points.first())
+                .map_or(0.0, |(_, watts)| *watts),
+        }
+    }
+}
+
+/// First-order thermal model of a GPU so the controller can run without hardware
+pub struct SimBackend {
+    pub config: SimConfig,
+    pub temp: f64,
+    pub fan_speed: u64,
+    pub elapsed: f64,
+    pub manual_control: bool,
+    last_step: Option<Instant>,
+}
+
+impl SimBackend {
+    pub fn new(config: SimConfig) -> Self {
+        SimBackend {
+            temp: config.initial_temp,
+            config,
+            fan_speed: 0,
+            elapsed: 0.0,
+            manual_control: false,
+            last_step: None,
+        }
+    }
+
+    /// Advances the model by `dt` seconds with the current fan speed
+    pub fn step(&mut self, dt: f64) {
+        let load = self.config.heat_load.watts_at(self.elapsed);
+        let conductance = self.config.idle_conductance
+            + self.config.fan_conductance * (self.fan_speed as f64 / 100.0);
+
+        // exact solution for constant inputs, stable for any step size
+        if conductance > 0.0 {
+            let steady_temp = self.config.ambient_temp + load / conductance;
+            let decay = (-conductance * dt / self.config.heat_capacity).exp();
+            self.temp = steady_temp + (self.temp - steady_temp) * decay;
+        } else {
+            self.temp += load * dt / self.config.heat_capacity;
+        }
+
+        self.elapsed += dt;
+    }
+
+    fn next_dt(&mut self) -> f64 {
+        if let Some(time_step) = self.config.time_step {
+            return time_step;
+        }
+
+        let now = Instant::now();
+        let dt = self
+            .last_step
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_step = Some(now);
+        dt
+    }
+}
+
+impl GpuBackend for SimBackend {
+    fn name(&self) -> &'static str {
+        "sim"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        let dt = self.next_dt();
+        self.step(dt);
+
+        Ok(GpuSnapshot {
+            temp: self.temp.round().clamp(0.0, 200.0) as u64,
+            fan_speed: self.fan_speed.clamp(0, 100),
+            power_draw: Some(self.config.heat_load.watts_at(self.elapsed)),
+            ..GpuSnapshot::default()
+        })
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        // a snapshot steps the model
+        Ok(self.fan_speed.clamp(0, 100))
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        Some(self.manual_control)
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.manual_control = true;
+        Ok(())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.manual_control = false;
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        if !self.manual_control {
+            return Err("Simulated GPU fan control has not been acquired".into());
+        }
+        self.fan_speed = speed.clamp(0, 100);
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use crate::backend::GpuBackend;
+use crate::config::{Config, HeatLoad, SimConfig};
+use crate::sim::SimBackend;
+use crate::thermalmanager::ThermalManager;
+
+fn fixed_step_config(heat_load: HeatLoad) -> SimConfig {
+    SimConfig {
+        heat_load,
+        time_step: Some(1.0),
+        ..SimConfig::default()
+    }
+}
+
+#[test]
+fn test_heat_load_profiles() {
+    let constant = HeatLoad::Constant { watts: 120.0 };
+    assert_eq!(constant.watts_at(0.0), 120.0);
+    assert_eq!(constant.watts_at(500.0), 120.0);
+
+    let step = HeatLoad::Step {
+        before: 50.0,
+        after: 250.0,
+        at: 30.0,
+    };
+    assert_eq!(step.watts_at(29.9), 50.0);
+    assert_eq!(step.watts_at(30.0), 250.0);
+
+    let script = HeatLoad::Script {
+        points: vec![(10.0, 80.0), (20.0, 200.0), (40.0, 0.0)],
+    };
+    assert_eq!(script.watts_at(0.0), 80.0, "Before the first point");
+    assert_eq!(script.watts_at(15.0), 80.0);
+    assert_eq!(script.watts_at(20.0), 200.0);
+    assert_eq!(script.watts_at(100.0), 0.0);
+
+    let empty = HeatLoad::Script { points: Vec::new() };
+    assert_eq!(empty.watts_at(5.0), 0.0);
+}
+
+#[test]
+fn test_sim_reaches_steady_state() {
+    let config = fixed_step_config(HeatLoad::Constant { watts: 200.0 });
+    let mut backend = SimBackend::new(config);
+    backend.acquire_fan_control().unwrap();
+
+    // fans stopped: 30 C ambient + 200 W / 2 W/C
+    for _ in 0..2000 {
+        backend.get_snapshot().unwrap();
+    }
+    assert_eq!(backend.get_snapshot().unwrap().temp, 130);
+
+    // full fans: 30 C ambient + 200 W / 10 W/C
+    backend.set_fan_speed(100).unwrap();
+    for _ in 0..2000 {
+        backend.get_snapshot().unwrap();
+    }
+    assert_eq!(backend.get_snapshot().unwrap().temp, 50);
+    assert_eq!(backend.get_snapshot().unwrap().fan_speed, 100);
+}
+
+#[test]
+fn test_sim_requires_fan_control() {
+    let mut backend = SimBackend::new(SimConfig::default());
+    assert!(backend.set_fan_speed(50).is_err());
+
+    backend.acquire_fan_control().unwrap();
+    backend.set_fan_speed(150).unwrap();
+    assert_eq!(backend.get_snapshot().unwrap().fan_speed, 100);
+}
+
+#[test]
+fn test_thermal_manager_with_sim() {
+    let config = Config {
+        fan_dwell_time: 0,
+        ..Config::default()
+    };
+    let sim_config = fixed_step_config(HeatLoad::Step {
+        before: 60.0,
+        after: 250.0,
+        at: 120.0,
+    });
+    let mut backend = SimBackend::new(sim_config);
+    backend.acquire_fan_control().unwrap();
+    let mut thermal_manager = ThermalManager::new(config.clone(), Box::new(backend));
+
+    fn tick(manager: &mut ThermalManager, count: usize) {
+        for _ in 0..count {
+            manager.update_temperature().unwrap();
+            manager.set_target_fan_speed().unwrap();
+        }
+    }
+
+    // light load stays under the first threshold and parks the fans at the floor
+    tick(&mut thermal_manager, 110);
+    assert!(thermal_manager.current_temp < config.temp_thresholds[0]);
+    assert_eq!(thermal_manager.current_fan_speed, config.fan_speed_floor);
+
+    // heavy load heats the GPU and the curve ramps the fans up to hold it
+    tick(&mut thermal_manager, 600);
+    assert!(thermal_manager.current_temp > config.temp_thresholds[1]);
+    assert!(thermal_manager.current_temp < config.temp_thresholds[4]);
+    assert!(thermal_manager.current_fan_speed > config.fan_speed_floor);
+}
+
+#[test]
+fn test_sim_config_round_trip() {
+    let config = Config {
+        sim: Some(fixed_step_config(HeatLoad::Script {
+            points: vec![(0.0, 80.0), (60.0, 250.0)],
+        })),
+        ..Config::default()
+    };
+
+    let serialized = toml::to_string(&config).unwrap();
+    let parsed: Config = toml::from_str(&serialized).unwrap();
+    let sim = parsed.sim.unwrap();
+    assert_eq!(sim.heat_load, config.sim.unwrap().heat_load);
+    assert_eq!(sim.time_step, Some(1.0));
+}
@@ -0,0 +1,271 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::backend::{GpuBackend, GpuSnapshot, NvidiaCliBackend};
+use crate::commands::{self, CommandError};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+type SampleResult = Result<GpuSnapshot, CommandError>;
+
+/// Keeps a long-lived sampling process running and publishes its latest parsed line
+pub struct SampleStream {
+    latest: Arc<Mutex<Option<SampleResult>>>,
+    last_line: Arc<Mutex<Option<Instant>>>,
+    restarts: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    child: Arc<Mutex<Option<Child>>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+fn sleep_unless_stopped(stop: &AtomicBool, duration: Duration) {
+    let tick = Duration::from_millis(50);
+    let mut slept = Duration::ZERO;
+    while slept < duration && !stop.load(Ordering::SeqCst) {
+        thread::sleep(tick.min(duration - slept));
+        slept += tick;
+    }
+}
+
+impl SampleStream {
+    pub fn spawn(
+        program: String,
+        args: Vec<String>,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let last_line = Arc::new(Mutex::new(None));
+        let restarts = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let child: Arc<Mutex<Option<Child>>> = Arc::new(Mutex::new(None));
+
+        let reader = {
+            let latest = Arc::clone(&latest);
+            let last_line = Arc::clone(&last_line);
+            let restarts = Arc::clone(&restarts);
+            let stop = Arc::clone(&stop);
+            let child_slot = Arc::clone(&child);
+
+            thread::spawn(move || {
+                let mut backoff = initial_backoff;
+                while !stop.load(Ordering::SeqCst) {
+                    match Command::new(&program)
+                        .args(&args)
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::null())
+                        .spawn()
+                    {
+                        Ok(mut process) => {
+                            let stdout = process.stdout.take();
+                            if let Ok(mut slot) = child_slot.lock() {
+                                // drop may have raced the spawn, don't leave the child running
+                                if stop.load(Ordering::SeqCst) {
+                                    let _ = process.kill();
+                                }
+                                *slot = Some(process);
+                            }
+
+                            if let Some(stdout) = stdout {
+                                for line in BufReader::new(stdout).lines() {
+                                    let Ok(line) = line else { break };
+                                    if line.trim().is_empty() {
+                                        continue;
+                                    }
+                                    if let Ok(mut sample) = latest.lock() {
+                                        *sample = Some(commands::parse_snapshot(&line));
+                                    }
+                                    if let Ok(mut updated) = last_line.lock() {
+                                        *updated = Some(Instant::now());
+                                    }
+                                    // a healthy stream resets the restart delay
+                                    backoff = initial_backoff;
+                                }
+                            }
+
+                            if let Ok(mut slot) = child_slot.lock() {
+                                if let Some(mut process) = slot.take() {
+                                    let _ = process.kill();
+                                    let _ = process.wait();
+                                }
+                            }
+                            // a dead child's last line isn't current any more
+                            if let Ok(mut sample) = latest.lock() {
+                                *sample = None;
+                            }
+                        }
+                        Err(e) => eprintln!("Failed to start {}: {}", program, e),
+                    }
+
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    eprintln!("{} stream exited, restarting in {:?}", program, backoff);
+                    sleep_unless_stopped(&stop, backoff);
+                    backoff = (backoff * 2).min(max_backoff);
+                    restarts.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+
+        SampleStream {
+            latest,
+            last_line,
+            restarts,
+            stop,
+            child,
+            reader: Some(reader),
+        }
+    }
+
+    /// The most recent line of the running child, including a parse failure so it isn't
+    /// masked by an older sample; `None` while no child has printed since the last (re)start
+    pub fn latest(&self) -> Option<SampleResult> {
+        self.latest.lock().ok().and_then(|sample| sample.clone())
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Time since the child last printed a line, `None` before the first one
+    pub fn since_last_line(&self) -> Option<Duration> {
+        self.last_line
+            .lock()
+            .ok()
+            .and_then(|updated| updated.map(|instant| instant.elapsed()))
+    }
+
+    /// Kills a hung child so the reader starts a fresh one
+    pub fn restart(&self) {
+        // the hung child's last sample must not be passed off as a fresh reading
+        if let Ok(mut sample) = self.latest.lock() {
+            *sample = None;
+        }
+        if let Ok(mut updated) = self.last_line.lock() {
+            // give the replacement a full timeout before it's judged stalled
+            *updated = Some(Instant::now());
+        }
+        if let Ok(mut slot) = self.child.lock() {
+            if let Some(process) = slot.as_mut() {
+                let _ = process.kill();
+            }
+        }
+    }
+}
+
+impl Drop for SampleStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // killing the child closes its stdout and unblocks the reader
+        if let Ok(mut slot) = self.child.lock() {
+            if let Some(process) = slot.as_mut() {
+                let _ = process.kill();
+            }
+        }
+        if let Some(reader) = self.reader.take() {
+            if reader.join().is_err() {
+                eprintln!("Sample stream reader panicked");
+            }
+        }
+    }
+}
+
+/// Reads telemetry from one persistent `nvidia-smi -lms` process instead of one per tick
+pub struct NvidiaStreamBackend {
+    stream: SampleStream,
+    cli: NvidiaCliBackend,
+    interval: Duration,
+}
+
+impl NvidiaStreamBackend {
+    /// Streams the GPU `cli` points at, which also handles fan writes
+    pub fn new(interval: Duration, cli: NvidiaCliBackend) -> Self {
+        let args = vec![
+            format!("--id={}", cli.gpu_id),
+            format!("--query-gpu={}", commands::SNAPSHOT_FIELDS.join(",")),
+            "--format=csv,noheader,nounits".to_string(),
+            format!("--loop-ms={}", interval.as_millis().max(100)),
+        ];
+
+        NvidiaStreamBackend {
+            stream: SampleStream::spawn(
+                "nvidia-smi".to_string(),
+                args,
+                INITIAL_BACKOFF,
+                MAX_BACKOFF,
+            ),
+            cli,
+            interval,
+        }
+    }
+}
+
+impl GpuBackend for NvidiaStreamBackend {
+    fn name(&self) -> &'static str {
+        "nvidia-stream"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        // a loop-mode nvidia-smi that stops printing is hung, not just slow
+        let stall_limit = self.interval + self.cli.settings.timeout;
+        if self
+            .stream
+            .since_last_line()
+            .is_some_and(|elapsed| elapsed > stall_limit)
+        {
+            self.stream.restart();
+            commands::record_timeout();
+            return Err(CommandError::Timeout("nvidia-smi".to_string(), stall_limit).into());
+        }
+
+        // the stream may not have produced a line yet right after startup or a restart
+        match self.stream.latest() {
+            Some(sample) => Ok(sample?),
+            None => self.cli.get_snapshot(),
+        }
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.cli.get_fan_count()
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.cli.acquire_fan_control()
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        let restarts = self.stream.restarts();
+        if restarts > 0 {
+            println!("nvidia-smi stream was restarted {} time(s)", restarts);
+        }
+        self.cli.release_fan_control()
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        self.cli.get_fan_rpms()
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        self.cli.get_fan_speeds()
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        self.cli.get_fan_control()
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.cli.set_fan_speed(speed)
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.cli.set_fan_speed_of(fan, speed)
+    }
+}
@@ -0,0 +1,115 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::stream::SampleStream;
+
+fn shell_stream(script: &str) -> SampleStream {
+    SampleStream::spawn(
+        "sh".to_string(),
+        vec!["-c".to_string(), script.to_string()],
+        Duration::from_millis(10),
+        Duration::from_millis(40),
+    )
+}
+
+fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn test_stream_publishes_latest_sample() {
+    let stream = shell_stream(
+        "echo '50, 40, 120.5, 30, 1500, 7000, 0x0'; \
+         echo '61, 45, 180.0, 90, 1800, 7000, 0x0'; exec sleep 5",
+    );
+
+    assert!(wait_for(Duration::from_secs(5), || stream
+        .latest()
+        .is_some_and(|s| s.is_ok_and(|s| s.temp == 61))));
+    let sample = stream.latest().unwrap().unwrap();
+    assert_eq!(sample.fan_speed, 45);
+    assert_eq!(sample.utilization, Some(90));
+    assert_eq!(stream.restarts(), 0);
+}
+
+#[test]
+fn test_stream_restarts_exited_child() {
+    let stream = shell_stream("echo '55, 50, 100.0, 10, 1000, 2000, 0x0'");
+
+    assert!(wait_for(Duration::from_secs(5), || stream.restarts() >= 3));
+    // each replacement publishes its own line again
+    assert!(wait_for(Duration::from_secs(5), || stream
+        .latest()
+        .is_some_and(|s| s.is_ok_and(|s| s.temp == 55))));
+}
+
+#[test]
+fn test_stream_drops_sample_of_exited_child() {
+    let stream = SampleStream::spawn(
+        "sh".to_string(),
+        vec![
+            "-c".to_string(),
+            "echo '55, 50, 100.0, 10, 1000, 2000, 0x0'".to_string(),
+        ],
+        Duration::from_secs(5),
+        Duration::from_secs(5),
+    );
+
+    // the child exits right after its line and isn't restarted for a while
+    assert!(wait_for(Duration::from_secs(5), || stream
+        .since_last_line()
+        .is_some()));
+    assert!(wait_for(Duration::from_secs(5), || stream
+        .latest()
+        .is_none()));
+}
+
+#[test]
+fn test_stream_missing_program_keeps_retrying() {
+    let stream = SampleStream::spawn(
+        "/nonexistent/nvidia-smi".to_string(),
+        Vec::new(),
+        Duration::from_millis(10),
+        Duration::from_millis(20),
+    );
+
+    assert!(wait_for(Duration::from_secs(5), || stream.restarts() >= 2));
+    assert!(stream.latest().is_none());
+}
+
+#[test]
+fn test_stream_drop_kills_child() {
+    let stream =
+        shell_stream("while true; do echo '60, 50, 100.0, 10, 1000, 2000, 0x0'; sleep 0.05; done");
+    assert!(wait_for(Duration::from_secs(5), || stream
+        .latest()
+        .is_some()));
+
+    let started = Instant::now();
+    drop(stream);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_stream_restart_kills_hung_child() {
+    let stream = shell_stream("echo '58, 50, 100.0, 10, 1000, 2000, 0x0'; exec sleep 5");
+    assert!(wait_for(Duration::from_secs(5), || stream
+        .since_last_line()
+        .is_some()));
+    assert_eq!(stream.restarts(), 0);
+
+    stream.restart();
+    assert!(
+        stream.latest().is_none(),
+        "The hung child's sample is stale"
+    );
+    assert!(stream.since_last_line().unwrap() < Duration::from_secs(1));
+    assert!(wait_for(Duration::from_secs(5), || stream.restarts() >= 1));
+}
@@ -1,55 +1,273 @@
 use std::collections::VecDeque;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
+use crate::backend::{GpuBackend, GpuSnapshot};
 use crate::commands;
-use crate::config::Config;
+use crate::config::{Config, ControlMode, FailsafeMode, Interpolation, Sensor, StallAction};
+use crate::pid::PidController;
 use chrono::prelude::*;
 
 type ThresholdPair = (u64, u64);
 type ThresholdWindow = (ThresholdPair, Option<ThresholdPair>);
 
+// how far the wall clock may run ahead of the monotonic clock before it counts as a suspend
+const SUSPEND_GAP: Duration = Duration::from_secs(5);
+
 pub fn get_cur_time() -> String {
     let dt: DateTime<Local> = Local::now();
     dt.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Speed curve followed by one fan
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanCurve {
+    pub thresholds: Vec<ThresholdPair>,
+    pub floor: u64,
+    pub ceiling: u64,
+}
+
+impl FanCurve {
+    /// Builds the curve for fan `index`, applying any per-fan overrides from `config.fans`
+    pub fn from_config(config: &Config, index: u64) -> Self {
+        let fan = config.fans.iter().find(|fan| fan.index == index);
+        let temps = fan
+            .and_then(|fan| fan.temp_thresholds.clone())
+            .unwrap_or_else(|| config.temp_thresholds.clone());
+        let speeds = fan
+            .and_then(|fan| fan.fan_speeds.clone())
+            .unwrap_or_else(|| config.fan_speeds.clone());
+
+        FanCurve {
+            thresholds: temps.into_iter().zip(speeds).collect(),
+            floor: fan
+                .and_then(|fan| fan.fan_speed_floor)
+                .unwrap_or(config.fan_speed_floor),
+            ceiling: fan
+                .and_then(|fan| fan.fan_speed_ceiling)
+                .unwrap_or(config.fan_speed_ceiling),
+        }
+    }
+}
+
+/// Reads a monotone cubic (PCHIP) curve through `thresholds` at `temp`, holding the first
+/// and last speeds beyond the ends; `None` without any thresholds
+pub fn pchip_speed(thresholds: &[ThresholdPair], temp: f64) -> Option<f64> {
+    let mut points: Vec<(f64, f64)> = thresholds
+        .iter()
+        .map(|&(thresh, speed)| (thresh as f64, speed as f64))
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    points.dedup_by(|a, b| a.0 == b.0);
+
+    let (first, last) = (*points.first()?, *points.last()?);
+    if temp <= first.0 {
+        return Some(first.1);
+    }
+    if temp >= last.0 {
+        return Some(last.1);
+    }
+
+    let widths: Vec<f64> = points.windows(2).map(|w| w[1].0 - w[0].0).collect();
+    let slopes: Vec<f64> = points
+        .windows(2)
+        .zip(&widths)
+        .map(|(w, width)| (w[1].1 - w[0].1) / width)
+        .collect();
+
+    // Fritsch-Carlson tangents: flat at local extrema, a weighted harmonic mean elsewhere
+    let tangent = |k: usize| -> f64 {
+        let n = slopes.len();
+        if n == 1 {
+            return slopes[0];
+        }
+        if k == 0 || k == n {
+            let (d0, d1, h0, h1) = if k == 0 {
+                (slopes[0], slopes[1], widths[0], widths[1])
+            } else {
+                (slopes[n - 1], slopes[n - 2], widths[n - 1], widths[n - 2])
+            };
+            let m = ((2.0 * h0 + h1) * d0 - h0 * d1) / (h0 + h1);
+            return if m.signum() != d0.signum() || d0 == 0.0 {
+                0.0
+            } else if d0.signum() != d1.signum() && m.abs() > 3.0 * d0.abs() {
+                3.0 * d0
+            } else {
+                m
+            };
+        }
+        let (d0, d1) = (slopes[k - 1], slopes[k]);
+        if d0 * d1 <= 0.0 {
+            return 0.0;
+        }
+        let (h0, h1) = (widths[k - 1], widths[k]);
+        let (w0, w1) = (2.0 * h1 + h0, h1 + 2.0 * h0);
+        (w0 + w1) / (w0 / d0 + w1 / d1)
+    };
+
+    let k = points.windows(2).position(|w| temp < w[1].0)?;
+    let (x0, y0) = points[k];
+    let (h, y1) = (widths[k], points[k + 1].1);
+    let t = (temp - x0) / h;
+    let (t2, t3) = (t * t, t * t * t);
+    Some(
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * tangent(k)
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * tangent(k + 1),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanState {
+    pub index: u64,
+    pub curve: FanCurve,
+    pub current_speed: u64,
+    pub target_speed: u64,
+    /// Last speed written to this fan
+    pub commanded_speed: Option<u64>,
+}
+
 pub struct ThermalManager {
-    pub gpu_id: u8,
+    pub backend: Box<dyn GpuBackend>,
+    /// Prefix for log lines, names the GPU when several are controlled
+    pub label: String,
+    pub snapshot: GpuSnapshot,
     pub samples: VecDeque<u64>,
     pub config: Config,
     pub temp_average: u64,
     pub current_temp: u64,
+    /// Sensor whose reading produced `current_temp`
+    pub active_sensor: Sensor,
+    sensor_fallback_warned: bool,
     pub last_adjustment_time: Option<Instant>,
     pub last_temp_time: Option<Instant>,
     pub current_fan_speed: u64,
     pub target_fan_speed: u64,
+    /// Last speed written to every fan, `None` until the first write or after per-fan writes
+    pub commanded_speed: Option<u64>,
+    pub last_control_check: Option<Instant>,
+    /// Monotonic and wall-clock time of the previous step, the monotonic clock stops in suspend
+    pub last_step_time: Option<(Instant, SystemTime)>,
+    /// Set on resume so the next step writes its speed even if it looks unchanged
+    pub force_apply: bool,
+    /// Individually driven fans, empty when every fan follows the main curve
+    pub fans: Vec<FanState>,
     pub smooth_mode: String,
+    /// Drives the fans in `pid` mode
+    pub pid: PidController,
+    pub last_pid_update: Option<Instant>,
+    /// Set while reads are failing and the fans are held at the fail-safe policy
+    pub failsafe: bool,
+    /// Set until the fail-safe policy has been written, a failed write is retried every step
+    pub failsafe_pending: bool,
+    /// Steps in a row whose fan writes failed
+    pub failed_writes: u64,
+    pub failed_reads: u64,
+    pub good_reads: u64,
+    /// When each fan started reporting 0 RPM while commanded above its floor
+    pub stalled_since: Vec<Option<Instant>>,
+    /// Fans reported as stalled, cleared once they spin again
+    pub stalled_fans: Vec<u64>,
 }
 
 impl ThermalManager {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config, mut backend: Box<dyn GpuBackend>) -> Self {
+        let fan_count = match config.fans.is_empty() {
+            true => 0,
+            false => backend.get_fan_count(),
+        };
+        // each fan's curve starts from that fan's own speed, the first fan's won't do
+        let per_fan_readable = fan_count <= 1
+            || backend
+                .get_fan_speeds()
+                .is_some_and(|speeds| speeds.len() as u64 >= fan_count);
+        let fans = if config.fans.is_empty() {
+            Vec::new()
+        } else if !per_fan_readable {
+            eprintln!(
+                "The {} backend can't read the speed of each fan, per-fan curves are disabled \
+                 and every fan follows the main curve",
+                backend.name()
+            );
+            Vec::new()
+        } else {
+            for fan in config.fans.iter().filter(|fan| fan.index >= fan_count) {
+                eprintln!(
+                    "Ignoring config for fan {}, the GPU only has {} fan(s)",
+                    fan.index, fan_count
+                );
+            }
+
+            (0..fan_count)
+                .map(|index| FanState {
+                    index,
+                    curve: FanCurve::from_config(&config, index),
+                    current_speed: 0,
+                    target_speed: config.fan_speed_floor,
+                    commanded_speed: None,
+                })
+                .collect()
+        };
+
         ThermalManager {
-            gpu_id: 0,
+            backend,
+            label: String::new(),
+            snapshot: GpuSnapshot::default(),
             samples: VecDeque::with_capacity(config.sampling_window_size),
             config: config.clone(),
             temp_average: 0,
             current_temp: 0,
+            active_sensor: Sensor::Core,
+            sensor_fallback_warned: false,
             last_adjustment_time: None,
             last_temp_time: None,
             current_fan_speed: 0,
             target_fan_speed: config.fan_speed_floor,
-            smooth_mode: if config.smooth_mode {
-                "~".to_string()
-            } else {
-                "".to_string()
+            commanded_speed: None,
+            last_control_check: None,
+            last_step_time: None,
+            force_apply: false,
+            fans,
+            smooth_mode: match (config.mode, config.smooth_mode) {
+                (ControlMode::Pid, _) => "pid ".to_string(),
+                (ControlMode::Curve, true) => "~".to_string(),
+                (ControlMode::Curve, false) => "".to_string(),
             },
+            pid: PidController::from_config(&config),
+            last_pid_update: None,
+            failsafe: false,
+            failsafe_pending: false,
+            failed_writes: 0,
+            failed_reads: 0,
+            good_reads: 0,
+            stalled_since: Vec::new(),
+            stalled_fans: Vec::new(),
         }
     }
 
-    pub fn update_temperature(&mut self) {
-        self.current_temp = commands::get_gpu_temp(&self.gpu_id);
+    /// Reads the backend, a failed read leaves the previous snapshot and samples untouched
+    pub fn update_temperature(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.snapshot = self.backend.get_snapshot()?;
+        let (sensor, temp) = self.select_sensor_temp();
+        self.active_sensor = sensor;
+        self.current_temp = temp;
         self.last_temp_time = Some(Instant::now());
-        self.current_fan_speed = commands::get_fan_speed(&self.gpu_id);
+        self.current_fan_speed = self.snapshot.fan_speed;
+        if self.fans.len() > 1 && self.snapshot.fan_speeds.is_empty() {
+            if let Some(speeds) = self.backend.get_fan_speeds() {
+                self.snapshot.fan_speeds = speeds;
+            }
+        }
+        let single_fan = self.fans.len() == 1;
+        for fan in self.fans.iter_mut() {
+            match self.snapshot.fan_speeds.get(fan.index as usize) {
+                Some(speed) => fan.current_speed = *speed,
+                // the snapshot's speed is only this fan's when the GPU has just the one
+                None if single_fan => fan.current_speed = self.snapshot.fan_speed,
+                // keep the last reading rather than take another fan's
+                None => {}
+            }
+        }
         self.samples.push_back(self.current_temp);
         if self.samples.len() > self.config.sampling_window_size {
             self.samples.pop_front();
@@ -62,6 +280,324 @@ impl ThermalManager {
         } else {
             self.temp_average = self.calculate_wma();
         }
+
+        Ok(())
+    }
+
+    /// Picks the hottest configured sensor the backend reported, falling back to the core
+    fn select_sensor_temp(&mut self) -> (Sensor, u64) {
+        let hottest = self
+            .config
+            .sensor
+            .sensors()
+            .into_iter()
+            .filter_map(|sensor| Some((sensor, self.snapshot.sensor_temp(sensor)?)))
+            .max_by_key(|&(_, temp)| temp);
+
+        match hottest {
+            Some(reading) => reading,
+            None => {
+                if !self.sensor_fallback_warned {
+                    eprintln!(
+                        "The {} backend doesn't report any configured sensor, using the core temperature",
+                        self.backend.name()
+                    );
+                    self.sensor_fallback_warned = true;
+                }
+                (Sensor::Core, self.snapshot.temp)
+            }
+        }
+    }
+
+    /// Runs one step, a failed fan write is logged and retried on the next step; only
+    /// `max_write_failures` failed steps in a row are returned as an error
+    pub fn control_step(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.step() {
+            Ok(()) => {
+                self.failed_writes = 0;
+                Ok(())
+            }
+            Err(e) => {
+                self.failed_writes += 1;
+                eprintln!(
+                    "[{}] {}Failed to set fan speed ({} in a row): {}",
+                    get_cur_time(),
+                    self.label,
+                    self.failed_writes,
+                    e
+                );
+                if self.failed_writes >= self.config.max_write_failures.max(1) {
+                    return Err(e);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(suspended) = self.detect_resume() {
+            self.handle_resume(suspended);
+        }
+        if self.failsafe_pending {
+            self.apply_failsafe()?;
+        }
+
+        if let Err(e) = self.update_temperature() {
+            self.failed_reads += 1;
+            self.good_reads = 0;
+            eprintln!(
+                "[{}] {}Failed to read GPU ({} in a row): {} [{} timeout(s)]",
+                get_cur_time(),
+                self.label,
+                self.failed_reads,
+                e,
+                commands::timeout_count()
+            );
+            if !self.failsafe && self.failed_reads >= self.config.failsafe_after {
+                let reason = format!("{} failed reads", self.failed_reads);
+                self.enter_failsafe(&reason)?;
+            }
+            // keep the last commanded speed rather than acting on a bad reading
+            return Ok(());
+        }
+
+        self.failed_reads = 0;
+        let stalled =
+            self.check_fan_stall() && self.config.fan_stall_action == StallAction::Failsafe;
+        if stalled && !self.failsafe {
+            return self.enter_failsafe("a stalled fan");
+        }
+        if self.failsafe {
+            self.good_reads += 1;
+            if stalled || self.good_reads < self.config.failsafe_recover_after {
+                return Ok(());
+            }
+            self.leave_failsafe()?;
+        }
+
+        self.check_fan_control()?;
+        self.set_target_fan_speed()
+    }
+
+    /// Returns roughly how long the system was suspended since the previous call, if it was
+    pub fn detect_resume(&mut self) -> Option<Duration> {
+        let now = (Instant::now(), SystemTime::now());
+        let (last_instant, last_wall) = self.last_step_time.replace(now)?;
+
+        let monotonic = now.0.duration_since(last_instant);
+        let wall = now.1.duration_since(last_wall).unwrap_or_default();
+        (wall > monotonic + SUSPEND_GAP).then(|| wall - monotonic)
+    }
+
+    /// Drops readings and timers from before the suspend and marks the fans to be taken back
+    /// from the driver; the writes happen in the step, which retries them until they succeed
+    fn handle_resume(&mut self, suspended: Duration) {
+        println!(
+            "[{}] {}Veridian resumed after ~{}s asleep, re-taking fan control",
+            get_cur_time(),
+            self.label,
+            suspended.as_secs()
+        );
+        self.samples.clear();
+        self.last_adjustment_time = None;
+        self.last_control_check = None;
+        self.stalled_since.clear();
+        self.reset_pid();
+
+        match (self.failsafe, self.config.failsafe_mode) {
+            // the fans are meant to stay with the driver
+            (true, FailsafeMode::Auto) => {}
+            (true, FailsafeMode::Speed) => self.failsafe_pending = true,
+            (false, _) => self.force_apply = true,
+        }
+    }
+
+    /// Re-takes manual control and re-applies the commanded speeds when the driver has taken
+    /// the fans back or they drifted from what was commanded
+    fn check_fan_control(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let interval = Duration::from_secs(self.config.control_check_interval);
+        if interval.is_zero()
+            || self
+                .last_control_check
+                .is_some_and(|last| last.elapsed() < interval)
+        {
+            return Ok(());
+        }
+        self.last_control_check = Some(Instant::now());
+
+        let tolerance = self.config.fan_speed_tolerance;
+        let drifted = |reported: u64, commanded: Option<u64>| {
+            commanded.is_some_and(|commanded| reported.abs_diff(commanded) > tolerance)
+        };
+        let lost_control = self.backend.get_fan_control() == Some(false);
+        let drifted_fans: Vec<u64> = self
+            .fans
+            .iter()
+            .filter(|fan| drifted(fan.current_speed, fan.commanded_speed))
+            .map(|fan| fan.index)
+            .collect();
+        let drifted_main =
+            self.fans.is_empty() && drifted(self.current_fan_speed, self.commanded_speed);
+        if !lost_control && !drifted_main && drifted_fans.is_empty() {
+            return Ok(());
+        }
+
+        let reason = if lost_control {
+            "the driver took back fan control".to_string()
+        } else if drifted_main {
+            format!(
+                "fans report {} % instead of {} %",
+                self.current_fan_speed,
+                self.commanded_speed.unwrap_or_default()
+            )
+        } else {
+            format!("fan(s) {:?} drifted from the commanded speed", drifted_fans)
+        };
+        println!(
+            "[{}] {}Veridian re-taking fan control, {} [{}]",
+            get_cur_time(),
+            self.label,
+            reason,
+            self.status()
+        );
+
+        self.backend.acquire_fan_control()?;
+        if let Some(speed) = self.commanded_speed {
+            self.backend.set_fan_speed(speed)?;
+        }
+        for fan in self.fans.iter() {
+            if let Some(speed) = fan.commanded_speed {
+                self.backend.set_fan_speed_of(fan.index, speed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks for fans reporting 0 RPM above their floor, returns whether any has stalled
+    /// for longer than `fan_stall_time`
+    fn check_fan_stall(&mut self) -> bool {
+        if self.config.fan_stall_time == 0 {
+            return false;
+        }
+        // keep the previous verdict when the RPMs can't be read this time
+        let Some(rpms) = self.backend.get_fan_rpms() else {
+            return !self.stalled_fans.is_empty();
+        };
+
+        let stall_time = Duration::from_secs(self.config.fan_stall_time);
+        self.stalled_since.resize(rpms.len(), None);
+        for (index, rpm) in rpms.into_iter().enumerate() {
+            let fan = index as u64;
+            // the driver reports the speed it's driving each fan at
+            let commanded = self
+                .snapshot
+                .fan_speeds
+                .get(index)
+                .copied()
+                .unwrap_or(self.snapshot.fan_speed);
+            let floor = self
+                .fans
+                .get(index)
+                .map_or(self.config.fan_speed_floor, |fan| fan.curve.floor);
+
+            if rpm == 0 && commanded > floor {
+                let since = *self.stalled_since[index].get_or_insert_with(Instant::now);
+                if since.elapsed() >= stall_time && !self.stalled_fans.contains(&fan) {
+                    eprintln!(
+                        "[{}] {}Fan {} reports 0 RPM at {} % for {}s, it may be stalled or failing",
+                        get_cur_time(),
+                        self.label,
+                        fan,
+                        commanded,
+                        since.elapsed().as_secs()
+                    );
+                    self.stalled_fans.push(fan);
+                }
+            } else {
+                self.stalled_since[index] = None;
+                if self.stalled_fans.contains(&fan) {
+                    println!(
+                        "[{}] {}Fan {} is spinning again: {} RPM",
+                        get_cur_time(),
+                        self.label,
+                        fan,
+                        rpm
+                    );
+                    self.stalled_fans.retain(|stalled| *stalled != fan);
+                }
+            }
+        }
+
+        !self.stalled_fans.is_empty()
+    }
+
+    fn enter_failsafe(&mut self, reason: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.failsafe = true;
+        self.failsafe_pending = true;
+        match self.config.failsafe_mode {
+            FailsafeMode::Speed => println!(
+                "[{}] {}Veridian entering fail-safe after {}: {} %T",
+                get_cur_time(),
+                self.label,
+                reason,
+                self.config.failsafe_speed.min(100)
+            ),
+            FailsafeMode::Auto => println!(
+                "[{}] {}Veridian entering fail-safe after {}: driver auto control",
+                get_cur_time(),
+                self.label,
+                reason
+            ),
+        }
+        self.apply_failsafe()
+    }
+
+    /// Writes the fail-safe policy, `failsafe_pending` stays set until it succeeds
+    fn apply_failsafe(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.config.failsafe_mode {
+            FailsafeMode::Speed => {
+                let speed = self.config.failsafe_speed.min(100);
+                // a resume may have handed the fans back to the driver
+                self.backend.acquire_fan_control()?;
+                self.backend.set_fan_speed(speed)?;
+                self.target_fan_speed = speed;
+                self.commanded_speed = Some(speed);
+                for fan in self.fans.iter_mut() {
+                    fan.commanded_speed = None;
+                }
+            }
+            FailsafeMode::Auto => self.backend.release_fan_control()?,
+        }
+        self.failsafe_pending = false;
+        Ok(())
+    }
+
+    fn leave_failsafe(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        println!(
+            "[{}] {}Veridian leaving fail-safe after {} good reads",
+            get_cur_time(),
+            self.label,
+            self.good_reads
+        );
+        if self.config.failsafe_mode == FailsafeMode::Auto {
+            self.backend.acquire_fan_control()?;
+        }
+        self.failsafe = false;
+        self.failsafe_pending = false;
+        self.good_reads = 0;
+        // the fail-safe speed says nothing about what holds the target
+        self.reset_pid();
+        // the curve takes over right away instead of waiting out the dwell time
+        self.last_adjustment_time = None;
+        Ok(())
+    }
+
+    /// Snapshot summary shown in the transition log, plus any subprocess timeouts so far
+    pub fn status(&self) -> String {
+        match commands::timeout_count() {
+            0 => self.snapshot.to_string(),
+            timeouts => format!("{}, {} timeout(s)", self.snapshot, timeouts),
+        }
     }
 
     pub fn generate_thresholds_and_speeds(&mut self) -> Vec<(u64, u64)> {
@@ -84,18 +620,31 @@ impl ThermalManager {
         (temp_average / weight_sum) as u64
     }
 
+    fn main_curve(&self, thresholds: Vec<ThresholdPair>) -> FanCurve {
+        FanCurve {
+            thresholds,
+            floor: self.config.fan_speed_floor,
+            ceiling: self.config.fan_speed_ceiling,
+        }
+    }
+
     pub fn select_nearest_fan_speed(&mut self, thresholds: Vec<(u64, u64)>) -> u64 {
-        let mut nearest_speed = self.config.fan_speed_floor;
+        let curve = self.main_curve(thresholds);
+        self.nearest_speed_on(&curve)
+    }
+
+    fn nearest_speed_on(&self, curve: &FanCurve) -> u64 {
+        let mut nearest_speed = curve.floor;
 
         // Iterate in reverse to check higher thresholds first
-        for (thresh, speed) in thresholds.into_iter().rev() {
+        for &(thresh, speed) in curve.thresholds.iter().rev() {
             if self.current_temp >= thresh {
                 nearest_speed = speed;
                 break;
             }
         }
 
-        nearest_speed.clamp(self.config.fan_speed_floor, self.config.fan_speed_ceiling)
+        nearest_speed.clamp(curve.floor, curve.ceiling)
     }
 
     fn get_dwell_time(&mut self) -> bool {
@@ -117,10 +666,10 @@ impl ThermalManager {
 
         for &(thresh, speed) in thresholds {
             if thresh <= current_temp {
-                if lower_threshold.map_or(true, |(lt, _)| thresh > lt) {
+                if lower_threshold.is_none_or(|(lt, _)| thresh > lt) {
                     lower_threshold = Some((thresh, speed));
                 }
-            } else if upper_threshold.map_or(true, |(ut, _)| thresh < ut) {
+            } else if upper_threshold.is_none_or(|(ut, _)| thresh < ut) {
                 upper_threshold = Some((thresh, speed));
             }
         }
@@ -134,22 +683,57 @@ impl ThermalManager {
     }
 
     pub fn get_smooth_speed(&mut self, thresholds: &[ThresholdPair]) -> u64 {
-        let window = self.get_threshold_window(thresholds);
+        let curve = self.main_curve(thresholds.to_vec());
+        self.smooth_speed_on(&curve, self.current_fan_speed)
+    }
+
+    fn interpolation(&self) -> Interpolation {
+        match (self.config.interpolation, self.config.smooth_mode) {
+            (Some(interpolation), _) => interpolation,
+            (None, true) => Interpolation::Linear,
+            (None, false) => Interpolation::Step,
+        }
+    }
+
+    /// Speed the curve gives at the current temperature, before smoothing and clamping
+    fn curve_target(&self, curve: &FanCurve) -> Option<f64> {
+        match self.interpolation() {
+            Interpolation::Step => Some(self.nearest_speed_on(curve) as f64),
+            Interpolation::Linear => match self.get_threshold_window(&curve.thresholds) {
+                Some(((lower_thresh, lower_speed), Some((upper_thresh, upper_speed)))) => {
+                    let temp_range = (upper_thresh - lower_thresh) as f64;
+                    let speed_range = upper_speed as f64 - lower_speed as f64;
+                    let temp_diff = (self.current_temp - lower_thresh) as f64;
+
+                    Some(lower_speed as f64 + (temp_diff / temp_range) * speed_range)
+                }
+                Some(((_, lower_speed), None)) => Some(lower_speed as f64),
+                None => None,
+            },
+            Interpolation::Pchip => pchip_speed(&curve.thresholds, self.current_temp as f64),
+        }
+    }
 
-        let current_speed = self.current_fan_speed as f64;
+    fn smooth_speed_on(&self, curve: &FanCurve, current_speed: u64) -> u64 {
+        let current_speed = current_speed as f64;
         let max_step = self.config.smooth_mode_max_fan_step as f64;
         let hysteresis = self.config.hysteresis as f64;
-        let floor = self.config.fan_speed_floor as f64;
-        let ceiling = self.config.fan_speed_ceiling as f64;
+        let floor = curve.floor as f64;
+        let ceiling = curve.ceiling as f64;
+        // a weight covers 1/weight of the way to the target per adjustment, below 1 would overshoot
+        let incr_weight = self.config.smooth_mode_incr_weight.max(1.0);
+        let decr_weight = self.config.smooth_mode_decr_weight.max(1.0);
 
         let compute_new_speed = |target_speed: f64| -> u64 {
             let change = target_speed - current_speed;
+            // past the hysteresis always move by at least 1 %, or a heavy weight never arrives
+            let weighted = |weight: f64| (change / weight).abs().max(1.0).copysign(change);
             let limited_change = if change.abs() <= hysteresis {
                 0.0
             } else if change > 0.0 && max_step > 0.0 {
-                change.clamp(0.0, max_step)
+                weighted(incr_weight).clamp(0.0, max_step)
             } else {
-                change.clamp(-max_step, 0.0)
+                weighted(decr_weight).clamp(-max_step, 0.0)
             };
 
             (current_speed + limited_change)
@@ -157,30 +741,74 @@ impl ThermalManager {
                 .round() as u64
         };
 
-        match window {
-            Some(((lower_thresh, lower_speed), Some((upper_thresh, upper_speed)))) => {
-                let temp_range = (upper_thresh - lower_thresh) as f64;
-                let speed_range = (upper_speed - lower_speed) as f64;
-                let temp_diff = (self.current_temp - lower_thresh) as f64;
+        match self.curve_target(curve) {
+            Some(target_speed) => compute_new_speed(target_speed),
+            None => curve.floor,
+        }
+    }
 
-                let target_speed = lower_speed as f64 + (temp_diff / temp_range) * speed_range;
-                compute_new_speed(target_speed)
-            }
-            Some(((_, lower_speed), None)) => {
-                let target_speed = lower_speed as f64;
-                compute_new_speed(target_speed)
-            }
-            None => self.config.fan_speed_floor,
+    fn curve_speed(&self, curve: &FanCurve, current_speed: u64) -> u64 {
+        if self.config.smooth_mode {
+            return self.smooth_speed_on(curve, current_speed);
         }
+        match self.curve_target(curve) {
+            Some(target_speed) => target_speed
+                .clamp(curve.floor as f64, curve.ceiling as f64)
+                .round() as u64,
+            None => curve.floor,
+        }
+    }
+
+    fn reset_pid(&mut self) {
+        self.pid.reset();
+        self.last_pid_update = None;
+    }
+
+    /// Steps the PID loop on `temp_average`, timed by the gap since the previous step
+    fn pid_speed(&mut self) -> u64 {
+        let now = Instant::now();
+        let dt = match self.last_pid_update.replace(now) {
+            Some(last) => now.duration_since(last).as_secs_f64(),
+            None => self.config.global_delay as f64,
+        };
+        self.pid.update(
+            self.temp_average as f64,
+            dt,
+            self.current_fan_speed,
+            self.config.fan_speed_floor,
+            self.config.fan_speed_ceiling,
+        )
     }
 
     pub fn get_target_fan_speed(&mut self) -> u64 {
-        let thresholds = self.generate_thresholds_and_speeds();
+        if self.config.mode == ControlMode::Pid {
+            self.target_fan_speed = self.pid_speed();
+            // every fan runs the loop's output within its own limits
+            for fan in self.fans.iter_mut() {
+                fan.target_speed = self
+                    .target_fan_speed
+                    .clamp(fan.curve.floor, fan.curve.ceiling.max(fan.curve.floor));
+            }
+            return self.target_fan_speed;
+        }
 
-        if self.config.smooth_mode {
-            self.target_fan_speed = self.get_smooth_speed(&thresholds);
+        let thresholds = self.generate_thresholds_and_speeds();
+        self.target_fan_speed = if self.config.smooth_mode {
+            self.get_smooth_speed(&thresholds)
+        } else if self.interpolation() == Interpolation::Step {
+            self.select_nearest_fan_speed(thresholds)
         } else {
-            self.target_fan_speed = self.select_nearest_fan_speed(thresholds.clone());
+            let curve = self.main_curve(thresholds);
+            self.curve_speed(&curve, self.current_fan_speed)
+        };
+
+        let targets: Vec<u64> = self
+            .fans
+            .iter()
+            .map(|fan| self.curve_speed(&fan.curve, fan.current_speed))
+            .collect();
+        for (fan, target) in self.fans.iter_mut().zip(targets) {
+            fan.target_speed = target;
         }
 
         self.target_fan_speed
@@ -192,19 +820,73 @@ impl ThermalManager {
         if self.get_dwell_time() {
             return Ok(()); // Skip adjustment if within dwell time
         }
+        if self.force_apply {
+            // the driver has the fans after a resume, a failed acquire is retried next step
+            self.backend.acquire_fan_control()?;
+        }
 
-        if self.current_fan_speed != self.target_fan_speed {
+        if !self.fans.is_empty() {
+            return self.set_per_fan_speeds();
+        }
+
+        if self.current_fan_speed != self.target_fan_speed || self.force_apply {
             println!(
-                "[{}] Veridian transitioning state: {} C => {} %A -> {}{} %T",
+                "[{}] {}Veridian transitioning state: {} C ({}) => {} %A -> {}{} %T [{}]",
                 get_cur_time(),
+                self.label,
                 self.temp_average,
+                self.active_sensor,
                 self.current_fan_speed,
                 self.smooth_mode,
-                self.target_fan_speed
+                self.target_fan_speed,
+                self.status()
             );
-            commands::set_fan_speed(&self.gpu_id, self.target_fan_speed)?;
+            self.backend.set_fan_speed(self.target_fan_speed)?;
+            self.commanded_speed = Some(self.target_fan_speed);
             self.last_adjustment_time = Some(Instant::now());
+            self.force_apply = false;
+        }
+
+        Ok(())
+    }
+
+    fn set_per_fan_speeds(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let changed: Vec<(u64, u64, u64)> = self
+            .fans
+            .iter()
+            .filter(|fan| fan.current_speed != fan.target_speed || self.force_apply)
+            .map(|fan| (fan.index, fan.current_speed, fan.target_speed))
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let transitions: Vec<String> = changed
+            .iter()
+            .map(|(index, current, target)| {
+                format!(
+                    "fan{}: {} %A -> {}{} %T",
+                    index, current, self.smooth_mode, target
+                )
+            })
+            .collect();
+        println!(
+            "[{}] {}Veridian transitioning state: {} C ({}) => {} [{}]",
+            get_cur_time(),
+            self.label,
+            self.temp_average,
+            self.active_sensor,
+            transitions.join(", "),
+            self.status()
+        );
+
+        for (index, _, target) in changed {
+            self.backend.set_fan_speed_of(index, target)?;
+            self.fans[index as usize].commanded_speed = Some(target);
         }
+        self.commanded_speed = None;
+        self.force_apply = false;
+        self.last_adjustment_time = Some(Instant::now());
 
         Ok(())
     }
@@ -1,12 +1,118 @@
 use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::config::Config;
-use crate::thermalmanager::ThermalManager;
+use crate::backend::{GpuBackend, GpuSnapshot};
+use crate::commands::CommandError;
+use crate::config::{
+    Config, FailsafeMode, FanConfig, Interpolation, Sensor, SensorSelection, StallAction,
+};
+use crate::thermalmanager::{self, FanCurve, ThermalManager};
+
+/// Where a mock reports each fan's speed: in the snapshot like NVML, through a separate query
+/// like nvidia-settings, or not at all
+#[derive(Default, Clone, Copy, PartialEq)]
+enum FanSpeedSource {
+    #[default]
+    Snapshot,
+    Query,
+    Unreadable,
+}
+
+#[derive(Default)]
+struct MockBackend {
+    temp: u64,
+    memory_temp: Option<u64>,
+    fan_speed: u64,
+    fan_speeds: Vec<u64>,
+    fan_speed_source: FanSpeedSource,
+    // shared so tests can flip them after handing the backend to a manager
+    fail_reads: Arc<AtomicBool>,
+    fail_writes: Arc<AtomicBool>,
+    manual_control: Arc<AtomicBool>,
+    fan_rpms: Arc<Mutex<Option<Vec<u64>>>>,
+}
+
+impl GpuBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        if self.fail_reads.load(Ordering::SeqCst) {
+            return Err(CommandError::Unsupported("temperature.gpu", "[N/A]".to_string()).into());
+        }
+        Ok(GpuSnapshot {
+            temp: self.temp,
+            memory_temp: self.memory_temp,
+            fan_speed: self.fan_speed,
+            fan_speeds: match self.fan_speed_source {
+                FanSpeedSource::Snapshot => self.fan_speeds.clone(),
+                _ => Vec::new(),
+            },
+            ..GpuSnapshot::default()
+        })
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.fan_speeds.len().max(1) as u64
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        match self.fan_speed_source {
+            FanSpeedSource::Unreadable => None,
+            _ => Some(self.fan_speeds.clone()),
+        }
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        self.fan_rpms.lock().unwrap().clone()
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        Some(self.manual_control.load(Ordering::SeqCst))
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.fail_writes.load(Ordering::SeqCst) {
+            return Err("fan write failed".into());
+        }
+        self.manual_control.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.manual_control.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        if self.fail_writes.load(Ordering::SeqCst) {
+            return Err("fan write failed".into());
+        }
+        self.fan_speed = speed;
+        Ok(())
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        if self.fail_writes.load(Ordering::SeqCst) {
+            return Err("fan write failed".into());
+        }
+        self.fan_speeds[fan as usize] = speed;
+        Ok(())
+    }
+}
+
+fn mock_manager(config: Config) -> ThermalManager {
+    ThermalManager::new(config, Box::new(MockBackend::default()))
+}
 
 #[test]
 fn test_select_nearest_fan_speed() {
     let config = Config::default();
-    let mut thermal_manager = ThermalManager::new(config.clone());
+    let mut thermal_manager = mock_manager(config.clone());
 
     let test_thresholds = vec![(40, 46), (50, 55), (60, 62), (74, 80), (82, 100)];
     let test_cases = vec![
@@ -68,7 +174,7 @@ fn test_select_nearest_fan_speed() {
 #[test]
 fn test_calculate_wma() {
     let config = Config::default();
-    let mut thermal_manager = ThermalManager::new(config);
+    let mut thermal_manager = mock_manager(config);
 
     // Test with varying temperatures
     thermal_manager.samples = VecDeque::from(vec![40, 50, 60, 70, 80]);
@@ -101,8 +207,13 @@ fn test_calculate_wma() {
 
 #[test]
 fn test_get_smooth_speed() {
-    let config = Config::default();
-    let mut thermal_manager = ThermalManager::new(config);
+    // neutral weights, test_smooth_mode_weights covers them
+    let config = Config {
+        smooth_mode_incr_weight: 1.0,
+        smooth_mode_decr_weight: 1.0,
+        ..Config::default()
+    };
+    let mut thermal_manager = mock_manager(config);
     let thresholds = thermal_manager.generate_thresholds_and_speeds();
 
     // Test cases: (current_temp, current_fan_speed, expected_result)
@@ -111,7 +222,8 @@ fn test_get_smooth_speed() {
         (65, 55, 60),  // Increasing temperature
         (67, 60, 60),  // Test relative stability
         (68, 57, 62),  // At upper threshold
-        (83, 90, 100), // Test speed ceiling
+        (83, 90, 90),  // 92.5 is within the hysteresis of 90
+        (86, 90, 100), // Test speed ceiling
         (94, 90, 100), // Beyond max threshold
         (68, 50, 60),  // Max step limit (increase)
         (48, 60, 50),  // Max step limit (decrease)
@@ -132,3 +244,641 @@ fn test_get_smooth_speed() {
         );
     }
 }
+
+// adjustments needed to go from `from` to within the hysteresis of the curve at `temp`
+fn steps_to_settle(config: Config, temp: u64, from: u64) -> (u64, Vec<u64>) {
+    let mut thermal_manager = mock_manager(config);
+    let thresholds = thermal_manager.generate_thresholds_and_speeds();
+    thermal_manager.current_temp = temp;
+    thermal_manager.current_fan_speed = from;
+
+    let mut speeds = Vec::new();
+    loop {
+        let speed = thermal_manager.get_smooth_speed(&thresholds);
+        if speed == thermal_manager.current_fan_speed {
+            return (speeds.len() as u64, speeds);
+        }
+        speeds.push(speed);
+        thermal_manager.current_fan_speed = speed;
+    }
+}
+
+#[test]
+fn test_smooth_mode_weights() {
+    let weights = |incr, decr| Config {
+        smooth_mode_incr_weight: incr,
+        smooth_mode_decr_weight: decr,
+        ..Config::default()
+    };
+
+    // ramping down from 100 % to the 46 % floor
+    let (light_steps, light) = steps_to_settle(weights(1.0, 1.0), 40, 100);
+    let (heavy_steps, heavy) = steps_to_settle(weights(1.0, 4.0), 40, 100);
+    assert_eq!(light, vec![90, 80, 70, 60, 50, 46]);
+    assert_eq!(heavy[..4], [90, 80, 72, 66]);
+    assert!(
+        heavy_steps > light_steps,
+        "decr_weight 4 took {} steps, 1 took {}",
+        heavy_steps,
+        light_steps
+    );
+    assert!(heavy.iter().all(|speed| *speed >= 46));
+    assert!(heavy.last().unwrap().abs_diff(46) <= 3);
+
+    // the decrease weight leaves ramping up alone, the increase weight slows it
+    let (up_steps, up) = steps_to_settle(weights(1.0, 4.0), 90, 46);
+    assert_eq!(up, vec![56, 66, 76, 86, 96, 100]);
+    let (slow_up_steps, slow_up) = steps_to_settle(weights(4.0, 1.0), 90, 46);
+    assert_eq!(slow_up[..2], [56, 66]);
+    assert!(slow_up_steps > up_steps);
+
+    // weights below 1 would overshoot the target, so they act like 1
+    assert_eq!(steps_to_settle(weights(0.25, 0.25), 40, 100).1, light);
+}
+
+#[test]
+fn test_pchip_speed() {
+    let thresholds = vec![(40, 30), (50, 30), (60, 80), (70, 100), (80, 100)];
+    assert_eq!(thermalmanager::pchip_speed(&[], 50.0), None);
+    assert_eq!(thermalmanager::pchip_speed(&thresholds, 20.0), Some(30.0));
+    assert_eq!(thermalmanager::pchip_speed(&thresholds, 95.0), Some(100.0));
+    for &(thresh, speed) in &thresholds {
+        let at = thermalmanager::pchip_speed(&thresholds, thresh as f64).unwrap();
+        assert!((at - speed as f64).abs() < 1e-9, "{} C gave {}", thresh, at);
+    }
+
+    // never overshoots a threshold and never turns back down
+    let mut previous = 0.0;
+    for tenth in 400..=800 {
+        let temp = tenth as f64 / 10.0;
+        let speed = thermalmanager::pchip_speed(&thresholds, temp).unwrap();
+        let window = thresholds
+            .windows(2)
+            .find(|w| temp <= w[1].0 as f64)
+            .unwrap();
+        assert!(
+            speed >= window[0].1 as f64 - 1e-9 && speed <= window[1].1 as f64 + 1e-9,
+            "{} C gave {}",
+            temp,
+            speed
+        );
+        assert!(speed >= previous - 1e-9, "{} C dropped to {}", temp, speed);
+        previous = speed;
+    }
+
+    // two points are a straight line
+    assert_eq!(
+        thermalmanager::pchip_speed(&[(40, 40), (60, 80)], 45.0),
+        Some(50.0)
+    );
+}
+
+#[test]
+fn test_interpolation_modes() {
+    let curve = |interpolation| Config {
+        smooth_mode: false,
+        interpolation,
+        temp_thresholds: vec![40, 60, 70],
+        fan_speeds: vec![46, 66, 100],
+        ..Config::default()
+    };
+    // (interpolation, temp, expected speed)
+    let cases = vec![
+        (None, 55, 46),
+        (Some(Interpolation::Step), 55, 46),
+        (Some(Interpolation::Linear), 55, 61),
+        (Some(Interpolation::Linear), 65, 83),
+        (Some(Interpolation::Pchip), 55, 58),
+        (Some(Interpolation::Pchip), 60, 66),
+        (Some(Interpolation::Pchip), 65, 80),
+        (Some(Interpolation::Pchip), 30, 46),
+    ];
+    for (interpolation, temp, expected) in cases {
+        let mut thermal_manager = mock_manager(curve(interpolation));
+        thermal_manager.current_temp = temp;
+        assert_eq!(
+            thermal_manager.get_target_fan_speed(),
+            expected,
+            "{:?} at {} C",
+            interpolation,
+            temp
+        );
+    }
+
+    // smooth mode still limits each step toward the interpolated target
+    let mut thermal_manager = mock_manager(Config {
+        smooth_mode: true,
+        ..curve(Some(Interpolation::Pchip))
+    });
+    thermal_manager.current_temp = 65;
+    thermal_manager.current_fan_speed = 60;
+    assert_eq!(thermal_manager.get_target_fan_speed(), 70);
+}
+
+#[test]
+fn test_set_target_fan_speed_uses_backend() {
+    let config = Config {
+        smooth_mode: false,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 70,
+        fan_speed: 46,
+        ..MockBackend::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    thermal_manager.update_temperature().unwrap();
+    assert_eq!(thermal_manager.current_temp, 70);
+    assert_eq!(thermal_manager.current_fan_speed, 46);
+
+    thermal_manager.set_target_fan_speed().unwrap();
+    assert_eq!(thermal_manager.target_fan_speed, 62);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        62
+    );
+
+    // A cooler reading inside the dwell time must not write again
+    thermal_manager.current_temp = 30;
+    thermal_manager.set_target_fan_speed().unwrap();
+    assert_eq!(thermal_manager.target_fan_speed, 46);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        62
+    );
+}
+
+#[test]
+fn test_fan_curve_overrides() {
+    let config = Config {
+        fans: vec![FanConfig {
+            index: 1,
+            temp_thresholds: Some(vec![40, 60]),
+            fan_speeds: Some(vec![30, 70]),
+            fan_speed_floor: Some(30),
+            ..FanConfig::default()
+        }],
+        ..Config::default()
+    };
+
+    let main_curve = FanCurve::from_config(&config, 0);
+    assert_eq!(main_curve.thresholds[0], (48, 46));
+    assert_eq!(main_curve.floor, config.fan_speed_floor);
+
+    let fan_curve = FanCurve::from_config(&config, 1);
+    assert_eq!(fan_curve.thresholds, vec![(40, 30), (60, 70)]);
+    assert_eq!(fan_curve.floor, 30);
+    assert_eq!(fan_curve.ceiling, config.fan_speed_ceiling);
+}
+
+#[test]
+fn test_per_fan_control() {
+    let config = Config {
+        smooth_mode: false,
+        fans: vec![
+            FanConfig {
+                index: 1,
+                temp_thresholds: Some(vec![40, 60]),
+                fan_speeds: Some(vec![30, 70]),
+                fan_speed_floor: Some(30),
+                ..FanConfig::default()
+            },
+            FanConfig {
+                index: 5,
+                ..FanConfig::default()
+            },
+        ],
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 62,
+        fan_speed: 40,
+        fan_speeds: vec![40, 35],
+        ..MockBackend::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+    assert_eq!(
+        thermal_manager.fans.len(),
+        2,
+        "Fans beyond the GPU are ignored"
+    );
+
+    thermal_manager.update_temperature().unwrap();
+    assert_eq!(thermal_manager.fans[0].current_speed, 40);
+    assert_eq!(thermal_manager.fans[1].current_speed, 35);
+
+    thermal_manager.set_target_fan_speed().unwrap();
+    assert_eq!(thermal_manager.fans[0].target_speed, 55);
+    assert_eq!(thermal_manager.fans[1].target_speed, 70);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speeds,
+        vec![55, 70]
+    );
+}
+
+#[test]
+fn test_per_fan_speed_sources() {
+    let config = Config {
+        smooth_mode: false,
+        fans: vec![FanConfig {
+            index: 1,
+            fan_speed_floor: Some(30),
+            ..FanConfig::default()
+        }],
+        ..Config::default()
+    };
+
+    // nvidia-settings reports each fan's speed outside the snapshot
+    let backend = MockBackend {
+        temp: 62,
+        fan_speed: 40,
+        fan_speeds: vec![40, 35],
+        fan_speed_source: FanSpeedSource::Query,
+        ..MockBackend::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config.clone(), Box::new(backend));
+    assert_eq!(thermal_manager.fans.len(), 2);
+    thermal_manager.update_temperature().unwrap();
+    assert_eq!(thermal_manager.fans[0].current_speed, 40);
+    assert_eq!(
+        thermal_manager.fans[1].current_speed, 35,
+        "Fan 1 must not take fan 0's speed"
+    );
+
+    // without them the fans can't be driven apart
+    let backend = MockBackend {
+        temp: 62,
+        fan_speed: 40,
+        fan_speeds: vec![40, 35],
+        fan_speed_source: FanSpeedSource::Unreadable,
+        ..MockBackend::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+    assert!(
+        thermal_manager.fans.is_empty(),
+        "Per-fan mode is turned off"
+    );
+    thermal_manager.update_temperature().unwrap();
+    thermal_manager.set_target_fan_speed().unwrap();
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        55
+    );
+}
+
+#[test]
+fn test_sensor_selection() {
+    let backend = MockBackend {
+        temp: 60,
+        memory_temp: Some(84),
+        ..MockBackend::default()
+    };
+    let config = Config {
+        sensor: SensorSelection::Single(Sensor::Memory),
+        ..Config::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+    thermal_manager.update_temperature().unwrap();
+    assert_eq!(thermal_manager.current_temp, 84);
+    assert_eq!(thermal_manager.active_sensor, Sensor::Memory);
+
+    // The hottest available reading wins, unsupported sensors are skipped
+    thermal_manager.config.sensor = SensorSelection::Max(vec![Sensor::Core, Sensor::Hotspot]);
+    thermal_manager.update_temperature().unwrap();
+    assert_eq!(thermal_manager.current_temp, 60);
+    assert_eq!(thermal_manager.active_sensor, Sensor::Core);
+
+    // Nothing configured is available, fall back to the core
+    thermal_manager.config.sensor = SensorSelection::Single(Sensor::Hotspot);
+    thermal_manager.update_temperature().unwrap();
+    assert_eq!(thermal_manager.current_temp, 60);
+    assert_eq!(thermal_manager.active_sensor, Sensor::Core);
+}
+
+#[test]
+fn test_failed_read_keeps_previous_state() {
+    let backend = MockBackend {
+        temp: 70,
+        fan_speed: 62,
+        fail_reads: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let mut thermal_manager = ThermalManager::new(Config::default(), Box::new(backend));
+    thermal_manager.current_temp = 65;
+    thermal_manager.samples.push_back(65);
+
+    let err = thermal_manager.update_temperature().unwrap_err();
+    assert!(err.to_string().contains("temperature.gpu"));
+    // a failed read must not be mistaken for 0 C
+    assert_eq!(thermal_manager.current_temp, 65);
+    assert_eq!(thermal_manager.samples, VecDeque::from(vec![65]));
+}
+
+#[test]
+fn test_failsafe_speed() {
+    let config = Config {
+        smooth_mode: false,
+        failsafe_after: 2,
+        failsafe_recover_after: 2,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 70,
+        fan_speed: 62,
+        ..MockBackend::default()
+    };
+    let fail_reads = Arc::clone(&backend.fail_reads);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    fail_reads.store(true, Ordering::SeqCst);
+    thermal_manager.control_step().unwrap();
+    assert!(!thermal_manager.failsafe, "One failed read is tolerated");
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.failsafe);
+
+    fail_reads.store(false, Ordering::SeqCst);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        100
+    );
+    thermal_manager.control_step().unwrap();
+    assert!(
+        thermal_manager.failsafe,
+        "One good read isn't enough to recover"
+    );
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        100
+    );
+
+    thermal_manager.control_step().unwrap();
+    assert!(!thermal_manager.failsafe);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        62
+    );
+}
+
+#[test]
+fn test_failsafe_write_retried() {
+    let config = Config {
+        failsafe_after: 1,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 70,
+        fan_speed: 62,
+        fail_reads: Arc::new(AtomicBool::new(true)),
+        fail_writes: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let fail_writes = Arc::clone(&backend.fail_writes);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    // a failed fail-safe write keeps the loop running and is retried
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.failsafe);
+    assert!(thermal_manager.failsafe_pending);
+    assert_eq!(thermal_manager.failed_writes, 1);
+
+    fail_writes.store(false, Ordering::SeqCst);
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.failsafe);
+    assert!(!thermal_manager.failsafe_pending);
+    assert_eq!(thermal_manager.failed_writes, 0);
+    assert_eq!(thermal_manager.commanded_speed, Some(100));
+}
+
+#[test]
+fn test_write_failures_limit() {
+    let config = Config {
+        smooth_mode: false,
+        max_write_failures: 3,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 68,
+        fan_speed: 46,
+        fail_writes: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    thermal_manager.control_step().unwrap();
+    thermal_manager.control_step().unwrap();
+    assert_eq!(thermal_manager.failed_writes, 2);
+    assert!(
+        thermal_manager.control_step().is_err(),
+        "The third failure in a row is fatal"
+    );
+}
+
+#[test]
+fn test_failsafe_auto() {
+    let config = Config {
+        failsafe_after: 1,
+        failsafe_recover_after: 1,
+        failsafe_mode: FailsafeMode::Auto,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 70,
+        fan_speed: 62,
+        fail_reads: Arc::new(AtomicBool::new(true)),
+        manual_control: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let fail_reads = Arc::clone(&backend.fail_reads);
+    let manual_control = Arc::clone(&backend.manual_control);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.failsafe);
+    assert!(!manual_control.load(Ordering::SeqCst));
+
+    fail_reads.store(false, Ordering::SeqCst);
+    thermal_manager.control_step().unwrap();
+    assert!(!thermal_manager.failsafe);
+    assert!(manual_control.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_fan_stall_failsafe() {
+    let config = Config {
+        smooth_mode: false,
+        failsafe_recover_after: 1,
+        fan_stall_time: 30,
+        fan_stall_action: StallAction::Failsafe,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 68,
+        fan_speed: 46,
+        fan_rpms: Arc::new(Mutex::new(Some(vec![0]))),
+        ..MockBackend::default()
+    };
+    let fan_rpms = Arc::clone(&backend.fan_rpms);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    // fans may stop at the floor, the first step raises them to 62 %
+    thermal_manager.control_step().unwrap();
+    assert_eq!(thermal_manager.stalled_since, vec![None]);
+
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.stalled_since[0].is_some());
+    assert!(!thermal_manager.failsafe, "A short stop isn't a stall yet");
+
+    thermal_manager.stalled_since[0] = Some(Instant::now() - Duration::from_secs(31));
+    thermal_manager.control_step().unwrap();
+    assert_eq!(thermal_manager.stalled_fans, vec![0]);
+    assert!(thermal_manager.failsafe);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        100
+    );
+
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.failsafe, "Held while the fan is stalled");
+
+    *fan_rpms.lock().unwrap() = Some(vec![1800]);
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.stalled_fans.is_empty());
+    assert_eq!(thermal_manager.stalled_since, vec![None]);
+    assert!(!thermal_manager.failsafe);
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        62
+    );
+}
+
+#[test]
+fn test_reasserts_lost_fan_control() {
+    let config = Config {
+        smooth_mode: false,
+        control_check_interval: 60,
+        fan_speed_tolerance: 5,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 68,
+        fan_speed: 46,
+        manual_control: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let manual_control = Arc::clone(&backend.manual_control);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    thermal_manager.control_step().unwrap();
+    assert_eq!(thermal_manager.commanded_speed, Some(62));
+
+    // the driver takes the fans back, the dwell time would otherwise hide it
+    manual_control.store(false, Ordering::SeqCst);
+    thermal_manager.backend.set_fan_speed(40).unwrap();
+    thermal_manager.control_step().unwrap();
+    assert!(
+        !manual_control.load(Ordering::SeqCst),
+        "Checks only run once per interval"
+    );
+
+    thermal_manager.last_control_check = None;
+    thermal_manager.control_step().unwrap();
+    assert!(manual_control.load(Ordering::SeqCst));
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        62
+    );
+
+    // small deviations are tolerated, larger ones are re-applied
+    thermal_manager.backend.set_fan_speed(58).unwrap();
+    thermal_manager.last_control_check = None;
+    thermal_manager.control_step().unwrap();
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        58
+    );
+
+    thermal_manager.backend.set_fan_speed(50).unwrap();
+    thermal_manager.last_control_check = None;
+    thermal_manager.control_step().unwrap();
+    assert_eq!(
+        thermal_manager.backend.get_snapshot().unwrap().fan_speed,
+        62
+    );
+}
+
+#[test]
+fn test_resume_from_suspend() {
+    let config = Config {
+        smooth_mode: false,
+        control_check_interval: 0,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 68,
+        fan_speed: 46,
+        manual_control: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let manual_control = Arc::clone(&backend.manual_control);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+
+    thermal_manager.control_step().unwrap();
+    assert!(thermal_manager.detect_resume().is_none());
+    thermal_manager.samples.extend([90, 90, 90]);
+
+    // the driver resets the fans while asleep, which looks like the old commanded speed
+    manual_control.store(false, Ordering::SeqCst);
+    thermal_manager.backend.set_fan_speed(62).unwrap();
+    thermal_manager.backend.release_fan_control().unwrap();
+    let (instant, wall) = thermal_manager.last_step_time.unwrap();
+    thermal_manager.last_step_time = Some((instant, wall - Duration::from_secs(600)));
+    let adjusted_before_suspend = thermal_manager.last_adjustment_time;
+
+    thermal_manager.control_step().unwrap();
+    assert!(manual_control.load(Ordering::SeqCst));
+    assert_eq!(
+        thermal_manager.samples,
+        VecDeque::from(vec![68]),
+        "Readings from before the suspend are dropped"
+    );
+    assert!(!thermal_manager.force_apply);
+    assert!(
+        thermal_manager.last_adjustment_time > adjusted_before_suspend,
+        "The speed is written right away despite the dwell time"
+    );
+}
+
+#[test]
+fn test_resume_write_retried() {
+    let config = Config {
+        smooth_mode: false,
+        control_check_interval: 0,
+        ..Config::default()
+    };
+    let backend = MockBackend {
+        temp: 68,
+        fan_speed: 46,
+        manual_control: Arc::new(AtomicBool::new(true)),
+        ..MockBackend::default()
+    };
+    let manual_control = Arc::clone(&backend.manual_control);
+    let fail_writes = Arc::clone(&backend.fail_writes);
+    let mut thermal_manager = ThermalManager::new(config, Box::new(backend));
+    thermal_manager.control_step().unwrap();
+
+    // the driver isn't ready to give the fans back right after the resume
+    thermal_manager.backend.release_fan_control().unwrap();
+    fail_writes.store(true, Ordering::SeqCst);
+    let (instant, wall) = thermal_manager.last_step_time.unwrap();
+    thermal_manager.last_step_time = Some((instant, wall - Duration::from_secs(600)));
+    thermal_manager.control_step().unwrap();
+    assert!(!manual_control.load(Ordering::SeqCst));
+    assert!(thermal_manager.force_apply, "The resume is still pending");
+
+    fail_writes.store(false, Ordering::SeqCst);
+    thermal_manager.control_step().unwrap();
+    assert!(manual_control.load(Ordering::SeqCst));
+    assert!(!thermal_manager.force_apply);
+    assert_eq!(thermal_manager.failed_writes, 0);
+}
@@ -0,0 +1,287 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::backend::{GpuBackend, GpuSnapshot};
+
+pub const TRACE_HEADER: &str = "timestamp,temp,fan_speed,commanded_speed";
+
+/// One tick of a recorded session, `timestamp` is in seconds since the trace started
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceSample {
+    pub timestamp: f64,
+    pub temp: u64,
+    pub fan_speed: u64,
+    pub commanded_speed: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(std::io::Error),
+    InvalidLine(usize, String),
+    Empty,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Io(err) => write!(f, "IO error: {}", err),
+            TraceError::InvalidLine(line, content) => {
+                write!(f, "Invalid trace line {}: '{}'", line, content)
+            }
+            TraceError::Empty => write!(f, "Trace file contains no samples"),
+        }
+    }
+}
+impl std::error::Error for TraceError {}
+
+impl TraceSample {
+    pub fn to_line(self) -> String {
+        let commanded = self
+            .commanded_speed
+            .map_or(String::new(), |speed| speed.to_string());
+        format!(
+            "{:.3},{},{},{}",
+            self.timestamp, self.temp, self.fan_speed, commanded
+        )
+    }
+
+    pub fn from_line(line_number: usize, line: &str) -> Result<Self, TraceError> {
+        let invalid = || TraceError::InvalidLine(line_number, line.to_string());
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() != 4 {
+            return Err(invalid());
+        }
+
+        let commanded_speed = if fields[3].is_empty() {
+            None
+        } else {
+            Some(fields[3].parse::<u64>().map_err(|_| invalid())?)
+        };
+
+        Ok(TraceSample {
+            timestamp: fields[0].parse::<f64>().map_err(|_| invalid())?,
+            temp: fields[1].parse::<u64>().map_err(|_| invalid())?,
+            fan_speed: fields[2].parse::<u64>().map_err(|_| invalid())?,
+            commanded_speed,
+        })
+    }
+}
+
+pub fn read_trace(path: &Path) -> Result<Vec<TraceSample>, TraceError> {
+    let contents = fs::read_to_string(path).map_err(TraceError::Io)?;
+    let samples = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#') && line != TRACE_HEADER
+        })
+        .map(|(i, line)| TraceSample::from_line(i + 1, line))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if samples.is_empty() {
+        return Err(TraceError::Empty);
+    }
+
+    Ok(samples)
+}
+
+/// Wraps a live backend and appends every tick it serves to a trace file
+pub struct TraceRecorder {
+    inner: Box<dyn GpuBackend>,
+    file: File,
+    started: Instant,
+    pending: Option<TraceSample>,
+    commanded_speed: Option<u64>,
+}
+
+impl TraceRecorder {
+    pub fn create(path: &Path, inner: Box<dyn GpuBackend>) -> Result<Self, TraceError> {
+        let mut file = File::create(path).map_err(TraceError::Io)?;
+        writeln!(file, "{}", TRACE_HEADER).map_err(TraceError::Io)?;
+
+        Ok(TraceRecorder {
+            inner,
+            file,
+            started: Instant::now(),
+            pending: None,
+            commanded_speed: None,
+        })
+    }
+
+    /// Writes out the previous tick once everything commanded during it is known
+    fn flush_pending(&mut self) {
+        if let Some(mut sample) = self.pending.take() {
+            // a tick without a write records no command, not the previous tick's
+            sample.commanded_speed = self.commanded_speed.take();
+            if let Err(e) = writeln!(self.file, "{}", sample.to_line()) {
+                eprintln!("Failed to write trace sample: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for TraceRecorder {
+    fn drop(&mut self) {
+        self.flush_pending();
+    }
+}
+
+impl GpuBackend for TraceRecorder {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        self.flush_pending();
+        self.commanded_speed = None;
+        // failed reads aren't recorded, a replay can't reproduce them
+        let snapshot = self.inner.get_snapshot()?;
+        self.pending = Some(TraceSample {
+            timestamp: self.started.elapsed().as_secs_f64(),
+            temp: snapshot.temp,
+            fan_speed: snapshot.fan_speed,
+            commanded_speed: None,
+        });
+        Ok(snapshot)
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        // the startup capture isn't a control tick, it doesn't get a row
+        self.inner.read_fan_speed()
+    }
+
+    fn get_fan_count(&mut self) -> u64 {
+        self.inner.get_fan_count()
+    }
+
+    fn next_delay(&self) -> Option<Duration> {
+        self.inner.next_delay()
+    }
+
+    fn get_fan_rpms(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_rpms()
+    }
+
+    fn get_fan_speeds(&mut self) -> Option<Vec<u64>> {
+        self.inner.get_fan_speeds()
+    }
+
+    fn get_fan_control(&mut self) -> Option<bool> {
+        self.inner.get_fan_control()
+    }
+
+    fn original_pwm_enable(&self) -> Option<u64> {
+        self.inner.original_pwm_enable()
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.acquire_fan_control()
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush_pending();
+        self.inner.release_fan_control()
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.inner.set_fan_speed(speed)?;
+        self.commanded_speed = Some(speed);
+        Ok(())
+    }
+
+    fn set_fan_speed_of(&mut self, fan: u64, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.inner.set_fan_speed_of(fan, speed)?;
+        // the trace keeps one commanded column, fan 0 stands in for the card
+        if fan == 0 {
+            self.commanded_speed = Some(speed);
+        }
+        Ok(())
+    }
+}
+
+/// Feeds a recorded trace back to the controller one sample per reading
+pub struct ReplayBackend {
+    pub samples: Vec<TraceSample>,
+    pub position: usize,
+    pub commanded: Vec<u64>,
+    finished: bool,
+}
+
+impl ReplayBackend {
+    pub fn new(samples: Vec<TraceSample>) -> Self {
+        ReplayBackend {
+            samples,
+            position: 0,
+            commanded: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, TraceError> {
+        Ok(ReplayBackend::new(read_trace(path)?))
+    }
+
+    fn current(&self) -> &TraceSample {
+        // position always indexes the sample served by the last snapshot
+        &self.samples[self.position.saturating_sub(1).min(self.samples.len() - 1)]
+    }
+}
+
+impl GpuBackend for ReplayBackend {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn get_snapshot(&mut self) -> Result<GpuSnapshot, Box<dyn Error>> {
+        if self.position < self.samples.len() {
+            self.position += 1;
+        } else if !self.finished {
+            // hold the final reading rather than dropping to 0 C
+            println!("Replay trace finished, holding the last sample");
+            self.finished = true;
+        }
+
+        let sample = self.current();
+        Ok(GpuSnapshot {
+            temp: sample.temp,
+            fan_speed: sample.fan_speed,
+            ..GpuSnapshot::default()
+        })
+    }
+
+    fn read_fan_speed(&mut self) -> Result<u64, Box<dyn Error>> {
+        // a snapshot would use up a sample, the replay must start at the first one
+        Ok(self.current().fan_speed)
+    }
+
+    fn next_delay(&self) -> Option<Duration> {
+        // follow the recorded intervals so time-based decisions play out as they did
+        let next = self.samples.get(self.position)?;
+        let gap = next.timestamp - self.current().timestamp;
+        Some(Duration::from_secs_f64(gap.max(0.0)))
+    }
+
+    fn acquire_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn release_fan_control(&mut self) -> Result<(), Box<dyn Error>> {
+        println!(
+            "Replayed {} of {} samples with {} fan adjustment(s)",
+            self.position,
+            self.samples.len(),
+            self.commanded.len()
+        );
+        Ok(())
+    }
+
+    fn set_fan_speed(&mut self, speed: u64) -> Result<(), Box<dyn Error>> {
+        self.commanded.push(speed);
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+
+use crate::backend::{GpuBackend, InitialFanState};
+use crate::config::{Config, HeatLoad, SimConfig};
+use crate::sim::SimBackend;
+use crate::thermalmanager::ThermalManager;
+use crate::trace::{self, ReplayBackend, TraceError, TraceRecorder, TraceSample};
+
+#[test]
+fn test_trace_line_round_trip() {
+    let sample = TraceSample {
+        timestamp: 12.5,
+        temp: 64,
+        fan_speed: 55,
+        commanded_speed: Some(60),
+    };
+    assert_eq!(sample.to_line(), "12.500,64,55,60");
+    assert_eq!(
+        TraceSample::from_line(1, &sample.to_line()).unwrap(),
+        sample
+    );
+
+    let uncommanded = TraceSample::from_line(2, "0.000,40,46,").unwrap();
+    assert_eq!(uncommanded.commanded_speed, None);
+
+    let invalid = TraceSample::from_line(3, "0.000,hot,46,");
+    assert!(matches!(invalid, Err(TraceError::InvalidLine(3, _))));
+}
+
+#[test]
+fn test_read_trace_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let trace_path = temp_dir.path().join("empty.csv");
+    fs::write(&trace_path, format!("{}\n\n", trace::TRACE_HEADER)).unwrap();
+    assert!(matches!(
+        trace::read_trace(&trace_path),
+        Err(TraceError::Empty)
+    ));
+
+    let missing = temp_dir.path().join("missing.csv");
+    assert!(matches!(
+        trace::read_trace(&missing),
+        Err(TraceError::Io(_))
+    ));
+}
+
+#[test]
+fn test_record_then_replay() {
+    let temp_dir = TempDir::new().unwrap();
+    let trace_path = temp_dir.path().join("session.csv");
+    let config = Config {
+        fan_dwell_time: 0,
+        ..Config::default()
+    };
+
+    // record a live session against the simulated GPU
+    let mut sim = SimBackend::new(SimConfig {
+        heat_load: HeatLoad::Constant { watts: 250.0 },
+        time_step: Some(2.0),
+        ..SimConfig::default()
+    });
+    sim.acquire_fan_control().unwrap();
+    let mut recorder = TraceRecorder::create(&trace_path, Box::new(sim)).unwrap();
+    // the startup capture is neither a recorded row nor a model step
+    InitialFanState::capture(&mut recorder);
+    let mut live = ThermalManager::new(config.clone(), Box::new(recorder));
+    let mut live_targets = Vec::new();
+    for _ in 0..40 {
+        live.update_temperature().unwrap();
+        live.set_target_fan_speed().unwrap();
+        live_targets.push(live.target_fan_speed);
+    }
+    drop(live);
+
+    let samples = trace::read_trace(&trace_path).unwrap();
+    assert_eq!(samples.len(), 40);
+    assert!(samples[0].temp < samples[39].temp);
+    // each row holds the write of its own tick, ticks without one hold none
+    for (sample, target) in samples.iter().zip(&live_targets) {
+        assert!(sample.commanded_speed.is_none_or(|speed| speed == *target));
+    }
+    assert!(samples
+        .iter()
+        .any(|sample| sample.commanded_speed.is_none()));
+    assert!(samples
+        .iter()
+        .any(|sample| sample.commanded_speed.is_some()));
+
+    // replaying the trace reproduces the same decisions
+    let first_speed = samples[0].fan_speed;
+    let mut replay = ReplayBackend::new(samples);
+    assert_eq!(InitialFanState::capture(&mut replay).fan_speed, first_speed);
+    assert_eq!(replay.position, 0, "The capture doesn't use up a sample");
+    let mut replayed = ThermalManager::new(config, Box::new(replay));
+    for expected in live_targets {
+        replayed.update_temperature().unwrap();
+        replayed.set_target_fan_speed().unwrap();
+        assert_eq!(replayed.target_fan_speed, expected);
+    }
+}
+
+#[test]
+fn test_replay_holds_last_sample() {
+    let samples = vec![
+        TraceSample {
+            timestamp: 0.0,
+            temp: 50,
+            fan_speed: 46,
+            commanded_speed: None,
+        },
+        TraceSample {
+            timestamp: 2.0,
+            temp: 72,
+            fan_speed: 50,
+            commanded_speed: Some(66),
+        },
+    ];
+    let mut backend = ReplayBackend::new(samples);
+
+    let first = backend.get_snapshot().unwrap();
+    assert_eq!((first.temp, first.fan_speed), (50, 46));
+    assert_eq!(backend.get_snapshot().unwrap().temp, 72);
+    let held = backend.get_snapshot().unwrap();
+    assert_eq!((held.temp, held.fan_speed), (72, 50));
+
+    backend.set_fan_speed(70).unwrap();
+    assert_eq!(backend.commanded, vec![70]);
+}
+
+#[test]
+fn test_replay_follows_recorded_timing() {
+    let samples = [0.0, 2.5, 4.0]
+        .into_iter()
+        .map(|timestamp| TraceSample {
+            timestamp,
+            temp: 50,
+            fan_speed: 46,
+            commanded_speed: None,
+        })
+        .collect();
+    let mut backend = ReplayBackend::new(samples);
+
+    backend.get_snapshot().unwrap();
+    assert_eq!(backend.next_delay(), Some(Duration::from_millis(2500)));
+    backend.get_snapshot().unwrap();
+    assert_eq!(backend.next_delay(), Some(Duration::from_millis(1500)));
+    backend.get_snapshot().unwrap();
+    assert_eq!(
+        backend.next_delay(),
+        None,
+        "A finished replay polls as usual"
+    );
+}